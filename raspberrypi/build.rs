@@ -0,0 +1,47 @@
+// Build info for `--version`: the git commit the binary was built from and the build time.
+
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let out = Command::new("git").args(args).output().ok()?;
+    let text = String::from_utf8(out.stdout).ok()?;
+    (out.status.success() && !text.trim().is_empty()).then(|| text.trim().to_string())
+}
+
+fn main() {
+    // outside a checkout (e.g. a source tarball) the hash is "unknown"
+    let mut hash = git(&["rev-parse", "--short=10", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    if git(&["status", "--porcelain", "--untracked-files=no"]).is_some() {
+        hash.push_str("-dirty");
+    }
+    println!("cargo:rustc-env=LIGHTSWARM_GIT_HASH={hash}");
+    let built = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    println!("cargo:rustc-env=LIGHTSWARM_BUILD_TIME={built}");
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+
+    // rebuilt when the sources change or another commit is checked out
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=proto");
+    for path in ["HEAD", "index"] {
+        if let Some(path) = git(&["rev-parse", "--git-path", path]) {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
+
+// The gRPC event service from proto/lightswarm.proto, compiled with the bundled protoc unless
+// PROTOC points at another one.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("bundled protoc");
+        std::env::set_var("PROTOC", protoc);
+    }
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/lightswarm.proto"], &["proto"])
+        .expect("proto/lightswarm.proto compiles");
+}
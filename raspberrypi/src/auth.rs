@@ -0,0 +1,61 @@
+// Optional HMAC-SHA256 tags: +++<inner>,<hex_tag>*** with the tag computed over <inner>.
+
+use crate::protocol::Markers;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &[u8], inner: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(inner.as_bytes());
+    mac
+}
+
+// +++<inner>,<tag>***
+pub fn sign_frame(inner: &str, secret: &[u8], markers: Markers) -> String {
+    let tag = hex::encode(mac(secret, inner).finalize().into_bytes());
+    markers.frame(&format!("{inner},{tag}"))
+}
+
+// The frame without its tag (ready for parse_message), or None for a missing or bad tag.
+pub fn verify_frame(payload: &str, secret: &[u8], markers: Markers) -> Option<String> {
+    let inner = markers.inner(payload)?;
+    let (signed, tag) = inner.rsplit_once(',')?;
+    let tag = hex::decode(tag.trim()).ok()?;
+    // constant-time comparison
+    mac(secret, signed).verify_slice(&tag).ok()?;
+    Some(markers.frame(signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::DEFAULT_MARKERS;
+
+    #[test]
+    fn signed_frames_verify() {
+        let frame = sign_frame("Master,abc,512", b"s3cret", DEFAULT_MARKERS);
+        assert!(frame.starts_with("+++Master,abc,512,"));
+        assert_eq!(
+            verify_frame(&frame, b"s3cret", DEFAULT_MARKERS).as_deref(),
+            Some("+++Master,abc,512***")
+        );
+    }
+
+    #[test]
+    fn bad_or_missing_tags_are_rejected() {
+        let frame = sign_frame("Master,abc,512", b"s3cret", DEFAULT_MARKERS);
+        assert_eq!(verify_frame(&frame, b"other", DEFAULT_MARKERS), None);
+        let spoofed = frame.replace(",512,", ",1024,");
+        assert_eq!(verify_frame(&spoofed, b"s3cret", DEFAULT_MARKERS), None);
+        assert_eq!(
+            verify_frame("+++Master,abc,512***", b"s3cret", DEFAULT_MARKERS),
+            None
+        );
+        assert_eq!(
+            verify_frame("+++Master,abc,512,zz***", b"s3cret", DEFAULT_MARKERS),
+            None
+        );
+    }
+}
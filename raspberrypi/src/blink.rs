@@ -0,0 +1,245 @@
+use crate::config::{BlinkConfig, BlinkCurve};
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+
+// ===== Blink floor (default of blink.min_ms) =====
+pub const MIN_BLINK_SECONDS: f64 = 0.005;
+
+// ===== Blink ceiling: no endpoint or cap may exceed an hour (keeps intervals in a Duration) =====
+pub const MAX_BLINK_SECONDS: f64 = 3600.0;
+
+// ===== Blink mapping (live-tunable via the control console) =====
+#[derive(Debug, Clone, Copy)]
+pub struct BlinkMap {
+    pub curve: BlinkCurve,
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+    pub min_seconds: f64,
+    pub max_seconds: f64,
+}
+
+impl BlinkMap {
+    pub fn set_map(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) -> Result<()> {
+        if ![x1, y1, x2, y2].iter().all(|v| v.is_finite()) {
+            bail!("set-map requires finite values (got {x1} {y1} {x2} {y2})");
+        }
+        if !(x1 >= 0.0 && x1 < x2) {
+            bail!("set-map requires 0 <= x1 < x2 (got x1={x1} x2={x2})");
+        }
+        if !(y1 > 0.0 && y2 > 0.0) {
+            bail!("set-map requires y1 > 0 and y2 > 0 (got y1={y1} y2={y2})");
+        }
+        if y1 > MAX_BLINK_SECONDS || y2 > MAX_BLINK_SECONDS {
+            bail!("set-map requires y1, y2 <= {MAX_BLINK_SECONDS} s (got y1={y1} y2={y2})");
+        }
+        self.x1 = x1;
+        self.y1 = y1;
+        self.x2 = x2;
+        self.y2 = y2;
+        Ok(())
+    }
+
+    pub fn set_bounds(&mut self, min_ms: f64, max_ms: f64) -> Result<()> {
+        if !(min_ms > 0.0 && min_ms <= max_ms) {
+            bail!("set-blink-bounds requires 0 < min_ms <= max_ms (got {min_ms} {max_ms})");
+        }
+        let max_allowed_ms = MAX_BLINK_SECONDS * 1000.0;
+        if max_ms > max_allowed_ms {
+            bail!("set-blink-bounds requires max_ms <= {max_allowed_ms} (got {max_ms})");
+        }
+        self.min_seconds = min_ms / 1000.0;
+        self.max_seconds = max_ms / 1000.0;
+        Ok(())
+    }
+}
+
+impl BlinkMap {
+    pub fn from_config(cfg: &BlinkConfig) -> Result<Self> {
+        let mut map = Self {
+            curve: cfg.mapping,
+            x1: 0.0,
+            y1: 0.0,
+            x2: 0.0,
+            y2: 0.0,
+            min_seconds: MIN_BLINK_SECONDS,
+            max_seconds: MAX_BLINK_SECONDS,
+        };
+        map.set_map(cfg.x1, cfg.y1, cfg.x2, cfg.y2)
+            .context("Invalid [blink] mapping in config")?;
+        map.set_bounds(cfg.min_ms, cfg.max_ms.unwrap_or(MAX_BLINK_SECONDS * 1000.0))
+            .context("Invalid [blink] bounds in config")?;
+        Ok(map)
+    }
+}
+
+// Where x lies along the curve: 0 at x1, 1 at x2, extrapolated outside them.
+fn curve_position(curve: BlinkCurve, x: f64, x1: f64, x2: f64) -> f64 {
+    match curve {
+        BlinkCurve::Linear => (x - x1) / (x2 - x1),
+        // ln(1 + x) keeps a reading of 0 finite
+        BlinkCurve::Log => (x.ln_1p() - x1.ln_1p()) / (x2.ln_1p() - x1.ln_1p()),
+    }
+}
+
+pub fn blink_interval_seconds(map: &BlinkMap, reading: f64) -> f64 {
+    let x = reading.clamp(0.0, map.x2);
+    let position = curve_position(map.curve, x, map.x1, map.x2);
+    let seconds = map.y1 + position * (map.y2 - map.y1);
+    seconds.clamp(map.min_seconds, map.max_seconds)
+}
+
+// The interval as a Duration; a map that slipped past validation falls back to the ceiling.
+pub fn blink_interval(map: &BlinkMap, reading: f64) -> Duration {
+    Duration::try_from_secs_f64(blink_interval_seconds(map, reading))
+        .unwrap_or(Duration::from_secs_f64(MAX_BLINK_SECONDS))
+}
+
+// Software-PWM duty cycle (percent) for a reading: linear over [x1, x2] -> [min_duty, 100].
+pub fn brightness_duty(map: &BlinkMap, reading: f64, min_duty: u8) -> u8 {
+    let min_duty = f64::from(min_duty.min(100));
+    let x = reading.clamp(map.x1, map.x2);
+    let fraction = (x - map.x1) / (map.x2 - map.x1);
+    (min_duty + fraction * (100.0 - min_duty)).round() as u8
+}
+
+// Color mode: per-channel duty (percent) for [red, green, blue], from red at x1 to green at x2.
+pub fn reading_color(map: &BlinkMap, reading: f64) -> [u8; 3] {
+    let x = reading.clamp(map.x1, map.x2);
+    let fraction = (x - map.x1) / (map.x2 - map.x1);
+    let red = ((1.0 - fraction) * 100.0).round() as u8;
+    let green = (fraction * 100.0).round() as u8;
+    [red, green, 0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_map() -> BlinkMap {
+        BlinkMap::from_config(&BlinkConfig::default()).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn mapping_endpoints() {
+        let map = default_map();
+        assert_close(blink_interval_seconds(&map, 24.0), 2.01);
+        assert_close(blink_interval_seconds(&map, 1024.0), 0.01);
+        assert_close(blink_interval_seconds(&map, 524.0), 1.01);
+    }
+
+    #[test]
+    fn readings_are_clamped_to_0_x2() {
+        let map = default_map();
+        // below x1 the line extrapolates up to reading 0, then stops
+        let at_zero = blink_interval_seconds(&map, 0.0);
+        assert_close(at_zero, 2.01 + 24.0 * 0.002);
+        assert_close(blink_interval_seconds(&map, -500.0), at_zero);
+        assert_close(blink_interval_seconds(&map, f64::MIN), at_zero);
+        assert_close(blink_interval_seconds(&map, 5000.0), 0.01);
+        assert_close(blink_interval_seconds(&map, f64::MAX), 0.01);
+    }
+
+    #[test]
+    fn never_below_the_5ms_floor() {
+        let mut map = default_map();
+        // a mapping whose far end would be (near) zero or negative
+        map.set_map(0.0, 1.0, 1024.0, 0.001).unwrap();
+        map.x2 = 2048.0;
+        for reading in [-1.0, 0.0, 512.0, 1000.0, 1024.0, 2048.0, f64::MAX] {
+            let seconds = blink_interval_seconds(&map, reading);
+            assert!(seconds >= MIN_BLINK_SECONDS, "reading {reading}: {seconds}");
+        }
+        assert_close(blink_interval_seconds(&map, 2048.0), MIN_BLINK_SECONDS);
+    }
+
+    #[test]
+    fn configured_bounds_floor_and_cap() {
+        let cfg = BlinkConfig {
+            min_ms: 50.0,
+            max_ms: Some(1500.0),
+            ..BlinkConfig::default()
+        };
+        let map = BlinkMap::from_config(&cfg).unwrap();
+        assert_close(blink_interval_seconds(&map, 24.0), 1.5);
+        assert_close(blink_interval_seconds(&map, 1024.0), 0.05);
+        assert_close(blink_interval_seconds(&map, 524.0), 1.01);
+    }
+
+    #[test]
+    fn log_mapping_keeps_the_endpoints() {
+        let mut map = default_map();
+        map.curve = BlinkCurve::Log;
+        assert_close(blink_interval_seconds(&map, 24.0), 2.01);
+        assert_close(blink_interval_seconds(&map, 1024.0), 0.01);
+        // the interval falls faster at low light than the linear mapping
+        let linear = blink_interval_seconds(&default_map(), 100.0);
+        assert!(blink_interval_seconds(&map, 100.0) < linear);
+        // the floor still applies
+        map.set_map(0.0, 1.0, 1024.0, 0.001).unwrap();
+        assert_close(blink_interval_seconds(&map, 1024.0), MIN_BLINK_SECONDS);
+    }
+
+    #[test]
+    fn color_runs_from_red_to_green() {
+        let map = default_map();
+        assert_eq!(reading_color(&map, 0.0), [100, 0, 0]);
+        assert_eq!(reading_color(&map, 24.0), [100, 0, 0]);
+        assert_eq!(reading_color(&map, 524.0), [50, 50, 0]);
+        assert_eq!(reading_color(&map, 1024.0), [0, 100, 0]);
+        assert_eq!(reading_color(&map, f64::MAX), [0, 100, 0]);
+    }
+
+    #[test]
+    fn bounds_cap_the_interval() {
+        let mut map = default_map();
+        map.set_bounds(50.0, 1000.0).unwrap();
+        assert_close(blink_interval_seconds(&map, 0.0), 1.0);
+        assert_close(blink_interval_seconds(&map, 1024.0), 0.05);
+        assert!(map.set_bounds(0.0, 10.0).is_err());
+        assert!(map.set_bounds(20.0, 10.0).is_err());
+    }
+
+    #[test]
+    fn huge_and_infinite_values_are_rejected() {
+        let mut map = default_map();
+        assert!(map.set_map(0.0, 1e20, 1024.0, 0.01).is_err());
+        assert!(map.set_map(0.0, f64::INFINITY, 1024.0, 0.01).is_err());
+        assert!(map.set_map(0.0, 1.0, f64::INFINITY, 0.01).is_err());
+        assert!(map.set_map(f64::NAN, 1.0, 1024.0, 0.01).is_err());
+        assert!(map.set_bounds(5.0, 1e20).is_err());
+        assert!(map.set_bounds(5.0, f64::INFINITY).is_err());
+        assert!(map.set_bounds(f64::INFINITY, f64::INFINITY).is_err());
+        // the rejected values left the map untouched
+        assert_close(blink_interval_seconds(&map, 24.0), 2.01);
+
+        let cfg = BlinkConfig {
+            y1: 1e20,
+            ..BlinkConfig::default()
+        };
+        assert!(BlinkMap::from_config(&cfg).is_err());
+        let cfg = BlinkConfig {
+            max_ms: Some(f64::INFINITY),
+            ..BlinkConfig::default()
+        };
+        assert!(BlinkMap::from_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn interval_never_exceeds_the_ceiling() {
+        // x1 and x2 almost equal: extrapolating down to reading 0 explodes without the cap
+        let mut map = default_map();
+        map.set_map(1000.0, 3600.0, 1000.001, 1.0).unwrap();
+        assert_close(blink_interval_seconds(&map, 0.0), MAX_BLINK_SECONDS);
+        assert_eq!(blink_interval(&map, 0.0), Duration::from_secs(3600));
+        // a map forced past validation still yields a Duration instead of panicking
+        map.max_seconds = f64::INFINITY;
+        map.y1 = 1e20;
+        assert_eq!(blink_interval(&map, 0.0), Duration::from_secs(3600));
+    }
+}
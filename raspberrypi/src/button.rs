@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+// Debounce: a released -> pressed transition counts as a press only once `window` has passed
+// since the last accepted press; transitions inside the window are contact bounce.
+pub fn debounced_press(
+    was_pressed: bool,
+    pressed: bool,
+    since_last_press: Option<Duration>,
+    window: Duration,
+) -> bool {
+    !was_pressed && pressed && since_last_press.is_none_or(|elapsed| elapsed >= window)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressKind {
+    // clears the in-memory state
+    Short,
+    // also clears the log and resets the swarm
+    Long,
+}
+
+pub fn classify_press(held: Duration, long_press: Duration) -> PressKind {
+    if held >= long_press {
+        PressKind::Long
+    } else {
+        PressKind::Short
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn first_press_is_accepted() {
+        assert!(debounced_press(false, true, None, WINDOW));
+    }
+
+    #[test]
+    fn only_the_pressing_transition_counts() {
+        assert!(!debounced_press(true, true, None, WINDOW));
+        assert!(!debounced_press(true, false, None, WINDOW));
+        assert!(!debounced_press(false, false, None, WINDOW));
+    }
+
+    #[test]
+    fn bounce_inside_the_window_is_ignored() {
+        let bounce = Some(Duration::from_millis(15));
+        assert!(!debounced_press(false, true, bounce, WINDOW));
+        let just_inside = Some(WINDOW - Duration::from_millis(1));
+        assert!(!debounced_press(false, true, just_inside, WINDOW));
+    }
+
+    #[test]
+    fn rearms_after_the_window() {
+        assert!(debounced_press(false, true, Some(WINDOW), WINDOW));
+        let later = Some(Duration::from_secs(5));
+        assert!(debounced_press(false, true, later, WINDOW));
+    }
+
+    #[test]
+    fn long_press_threshold_is_inclusive() {
+        let long = Duration::from_secs(2);
+        assert_eq!(
+            classify_press(Duration::from_millis(300), long),
+            PressKind::Short
+        );
+        assert_eq!(
+            classify_press(long - Duration::from_millis(1), long),
+            PressKind::Short
+        );
+        assert_eq!(classify_press(long, long), PressKind::Long);
+        assert_eq!(
+            classify_press(Duration::from_secs(10), long),
+            PressKind::Long
+        );
+    }
+}
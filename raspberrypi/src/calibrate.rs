@@ -0,0 +1,62 @@
+// --calibrate: blink mapping endpoints suggested from the readings seen during a calibration run.
+
+use crate::stats::SwarmStats;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+// (x1, x2): the lowest and highest reading of any swarm, or None without a usable range.
+pub fn suggest_range(stats: &HashMap<String, SwarmStats>) -> Option<(f64, f64)> {
+    let x1 = stats.values().map(|s| s.min).reduce(f64::min)?.max(0.0);
+    let x2 = stats.values().map(|s| s.max).reduce(f64::max)?;
+    (x1 < x2).then_some((x1, x2))
+}
+
+// The config file text with [blink] x1/x2 set; other keys are kept, comments are not.
+pub fn with_blink_range(text: &str, x1: f64, x2: f64) -> Result<String> {
+    let mut doc: toml::Table = text.parse().context("Invalid config file")?;
+    let blink = doc
+        .entry("blink")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let Some(blink) = blink.as_table_mut() else {
+        bail!("blink in the config file is not a table");
+    };
+    blink.insert("x1".to_string(), x1.into());
+    blink.insert("x2".to_string(), x2.into());
+    toml::to_string(&doc).context("Failed to serialize the config file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn range_spans_every_swarm() {
+        let mut a = SwarmStats::new(40.0);
+        a.record(700.0);
+        let stats = HashMap::from([
+            ("a".to_string(), a),
+            ("b".to_string(), SwarmStats::new(950.5)),
+        ]);
+        assert_eq!(suggest_range(&stats), Some((40.0, 950.5)));
+        // a single reading is no range
+        let stats = HashMap::from([("a".to_string(), SwarmStats::new(5.0))]);
+        assert_eq!(suggest_range(&stats), None);
+        assert_eq!(suggest_range(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn written_range_keeps_other_keys() {
+        let text = "[network]\nport = 4300\n\n[blink]\ny1 = 1.5\nx1 = 24.0\n";
+        let written = with_blink_range(text, 40.0, 950.0).unwrap();
+        let cfg: Config = toml::from_str(&written).unwrap();
+        assert_eq!(cfg.network.port, 4300);
+        assert_eq!(
+            (cfg.blink.x1, cfg.blink.y1, cfg.blink.x2),
+            (40.0, 1.5, 950.0)
+        );
+
+        let cfg: Config = toml::from_str(&with_blink_range("", 1.0, 2.0).unwrap()).unwrap();
+        assert_eq!((cfg.blink.x1, cfg.blink.x2), (1.0, 2.0));
+    }
+}
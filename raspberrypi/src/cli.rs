@@ -0,0 +1,394 @@
+use clap::Parser;
+use raspberrypi::config::{
+    AddressFamily, BatchReading, BlinkCurve, Config, LogFormat, LogTimestamp, OutputFormat,
+    PwmMode, RgbMode, ShutdownLeds, WireProtocol, CONFIG_FILE,
+};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+// `--version`: crate version, git commit (`-dirty` with uncommitted changes) and build time,
+// set by build.rs.
+pub const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("LIGHTSWARM_GIT_HASH"),
+    ", built ",
+    env!("LIGHTSWARM_BUILD_TIME"),
+    ")"
+);
+
+// Command-line flags override the matching config file keys.
+#[derive(Debug, Parser)]
+#[command(about = "LightSwarm Raspberry Pi UDP listener", version = VERSION)]
+pub struct Cli {
+    /// Config file (missing file = built-in defaults)
+    #[arg(long, default_value = CONFIG_FILE)]
+    pub config: PathBuf,
+
+    /// UDP port to listen on and send reset broadcasts to [config: network.port]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Wire format of received readings [config: network.protocol]
+    #[arg(long, value_enum)]
+    pub protocol: Option<WireProtocol>,
+
+    /// Longest wait for a UDP datagram in ms, at least 1 [config: network.read_timeout_ms]
+    #[arg(long)]
+    pub read_timeout_ms: Option<u64>,
+
+    /// Address family of the UDP socket [config: network.family]
+    #[arg(long, value_enum)]
+    pub family: Option<AddressFamily>,
+
+    /// Local interface address to bind the UDP socket to [config: network.bind_addr]
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Network interface for IPv6 link-local addresses and multicast [config: network.interface]
+    #[arg(long, value_name = "NAME")]
+    pub interface: Option<String>,
+
+    /// Marker that starts every frame, received and sent [config: network.start_marker]
+    #[arg(long, value_name = "MARKER", allow_hyphen_values = true)]
+    pub start_marker: Option<String>,
+
+    /// Marker that ends every frame, received and sent [config: network.end_marker]
+    #[arg(long, value_name = "MARKER", allow_hyphen_values = true)]
+    pub end_marker: Option<String>,
+
+    /// Serve Prometheus metrics on this HTTP port [config: network.metrics_port]
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+
+    /// Serve a live page and Server-Sent Events stream on this HTTP port [config: network.web_port]
+    #[arg(long)]
+    pub web_port: Option<u16>,
+
+    /// Stream the console events over gRPC on this port; needs a build with the "grpc" feature
+    /// [config: network.grpc_port]
+    #[arg(long)]
+    pub grpc_port: Option<u16>,
+
+    /// InfluxDB UDP listener to send readings to as line protocol [config: network.influx_udp]
+    #[arg(long)]
+    pub influx_udp: Option<SocketAddr>,
+
+    /// Relay each accepted reading to this host:port [config: network.forward_to]
+    #[arg(long, value_name = "ADDR")]
+    pub forward: Option<SocketAddr>,
+
+    /// MQTT broker to publish readings to [config: mqtt.host]
+    #[arg(long)]
+    pub mqtt_host: Option<String>,
+
+    /// MQTT broker port [config: mqtt.port]
+    #[arg(long)]
+    pub mqtt_port: Option<u16>,
+
+    /// MQTT topic prefix [config: mqtt.topic_prefix]
+    #[arg(long)]
+    pub mqtt_prefix: Option<String>,
+
+    /// Sensor log file [config: log.path]
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Log record format [config: log.format]
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// Timestamp format of log records [config: log.timestamp]
+    #[arg(long, value_enum)]
+    pub log_timestamp: Option<LogTimestamp>,
+
+    /// Log each swarm to its own sensor_<id> file next to the log file [config: log.per_swarm]
+    #[arg(long)]
+    pub log_per_swarm: bool,
+
+    /// Log each reading's blink interval and LED index too [config: log.blink_fields]
+    #[arg(long)]
+    pub log_blink_fields: bool,
+
+    /// Collapse repeated identical log records into one line with a (xN) count [config: log.dedup]
+    #[arg(long)]
+    pub dedup_log: bool,
+
+    /// Destination address for reset broadcasts [config: network.broadcast_addr]
+    #[arg(long)]
+    pub broadcast_addr: Option<Ipv4Addr>,
+
+    /// ACK every logged UDP reading back to its sender [config: network.udp_ack]
+    #[arg(long)]
+    pub ack: bool,
+
+    /// Join this multicast group and send resets to it instead of broadcasting [config: network.multicast_group]
+    #[arg(long)]
+    pub multicast: Option<IpAddr>,
+
+    /// Shared secret: accept only HMAC-tagged frames and sign resets [config: network.secret]
+    #[arg(long)]
+    pub secret: Option<String>,
+
+    /// Reading -> blink interval curve between the (x1,y1) and (x2,y2) anchors [config: blink.mapping]
+    #[arg(long, value_enum)]
+    pub mapping: Option<BlinkCurve>,
+
+    /// Blink from the mean of the Master's last N readings (1 = raw) [config: blink.smoothing_window]
+    #[arg(long, value_name = "N")]
+    pub smoothing: Option<NonZeroUsize>,
+
+    /// Shortest blink interval in ms, whatever the reading [config: blink.min_ms]
+    #[arg(long, value_name = "MS")]
+    pub blink_min_ms: Option<f64>,
+
+    /// Longest blink interval in ms, so slow readings don't look frozen [config: blink.max_ms]
+    #[arg(long, value_name = "MS")]
+    pub blink_max_ms: Option<f64>,
+
+    /// RGB pins: one LED per swarm, or one RGB LED colored by the reading or by the Master's
+    /// swarm [config: gpio.rgb]
+    #[arg(long, value_enum)]
+    pub rgb: Option<RgbMode>,
+
+    /// Software PWM brightness for the RGB LEDs [config: gpio.pwm]
+    #[arg(long, value_enum)]
+    pub pwm: Option<PwmMode>,
+
+    /// LEDs at exit: switched off, or left at their last state (not on Pi 1-4) [config: gpio.leds_on_shutdown]
+    #[arg(long, value_enum)]
+    pub leds_on_shutdown: Option<ShutdownLeds>,
+
+    /// Terminal output: text lines or newline-delimited JSON events [config: status.output]
+    #[arg(long, value_enum)]
+    pub output: Option<OutputFormat>,
+
+    /// Interval between STATUS lines in ms, 0 = none [config: status.print_ms]
+    #[arg(long)]
+    pub status_ms: Option<u64>,
+
+    /// Interval between heartbeat lines in ms, printed even with no traffic, 0 = none
+    /// [config: status.heartbeat_ms]
+    #[arg(long)]
+    pub heartbeat_ms: Option<u64>,
+
+    /// Suppress STATUS lines; EVENT, warning and error output is kept (same as --status-ms 0)
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Run without GPIO hardware, printing LED changes instead [config: gpio.simulate]
+    #[arg(long)]
+    pub simulate: bool,
+
+    /// Blink only the current Master's LED, not every recent Master's [config: master.single]
+    #[arg(long)]
+    pub single_master: bool,
+
+    /// Use exactly this gpiochip device instead of probing gpiochip4, then gpiochip0 [config: gpio.chip]
+    #[arg(long, value_name = "PATH")]
+    pub gpiochip: Option<PathBuf>,
+
+    /// Alarm when the Master's reading is at or below this [config: alarm.low]
+    #[arg(long, value_name = "READING")]
+    pub alarm_low: Option<f64>,
+
+    /// Alarm when the Master's reading is at or above this [config: alarm.high]
+    #[arg(long, value_name = "READING")]
+    pub alarm_high: Option<f64>,
+
+    /// Readings repeated more than this often in a row are sensor_stuck, 0 = off [config: reading.stuck_limit]
+    #[arg(long, value_name = "N")]
+    pub stuck_limit: Option<u32>,
+
+    /// Stop blinking a stuck Master's LED until its reading changes [config: reading.stuck_dark]
+    #[arg(long)]
+    pub stuck_dark: bool,
+
+    /// Blink from the last or the average reading of a batched frame [config: reading.batch]
+    #[arg(long, value_enum)]
+    pub batch_reading: Option<BatchReading>,
+
+    /// Skip the startup LED self-test [config: gpio.selftest = false]
+    #[arg(long)]
+    pub no_selftest: bool,
+
+    /// Print every gpiochip with its lines and their users, then exit
+    #[arg(long)]
+    pub list_gpiochips: bool,
+
+    /// Collect readings for this many seconds, then print suggested blink.x1/x2 and exit
+    #[arg(long, value_name = "SECS", conflicts_with = "replay")]
+    pub calibrate: Option<u64>,
+
+    /// With --calibrate, write the suggested x1/x2 to the config file
+    #[arg(long, requires = "calibrate")]
+    pub calibrate_write: bool,
+
+    /// Feed a saved sensor log or raw frame capture through the pipeline instead of listening
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// With --replay, wait out the gaps between the original timestamps
+    #[arg(long, requires = "replay")]
+    pub replay_pace: bool,
+
+    /// Receive, parse and log (to the temp dir) without driving GPIO or sending anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl Cli {
+    pub fn apply(&self, cfg: &mut Config) {
+        if let Some(port) = self.port {
+            cfg.network.port = port;
+        }
+        if let Some(protocol) = self.protocol {
+            cfg.network.protocol = protocol;
+        }
+        if let Some(ms) = self.read_timeout_ms {
+            cfg.network.read_timeout_ms = ms;
+        }
+        if let Some(family) = self.family {
+            cfg.network.family = family;
+        }
+        if let Some(addr) = self.bind {
+            cfg.network.bind_addr = Some(addr);
+        }
+        if let Some(name) = &self.interface {
+            cfg.network.interface = Some(name.clone());
+        }
+        if let Some(marker) = &self.start_marker {
+            cfg.network.start_marker = marker.clone();
+        }
+        if let Some(marker) = &self.end_marker {
+            cfg.network.end_marker = marker.clone();
+        }
+        if let Some(port) = self.metrics_port {
+            cfg.network.metrics_port = Some(port);
+        }
+        if let Some(port) = self.web_port {
+            cfg.network.web_port = Some(port);
+        }
+        if let Some(port) = self.grpc_port {
+            cfg.network.grpc_port = Some(port);
+        }
+        if let Some(addr) = self.influx_udp {
+            cfg.network.influx_udp = Some(addr);
+        }
+        if let Some(addr) = self.forward {
+            cfg.network.forward_to = Some(addr);
+        }
+        if let Some(host) = &self.mqtt_host {
+            cfg.mqtt.host = Some(host.clone());
+        }
+        if let Some(port) = self.mqtt_port {
+            cfg.mqtt.port = port;
+        }
+        if let Some(prefix) = &self.mqtt_prefix {
+            cfg.mqtt.topic_prefix = prefix.clone();
+        }
+        if let Some(path) = &self.log_file {
+            cfg.log.path = path.clone();
+        }
+        if let Some(format) = self.log_format {
+            cfg.log.format = format;
+        }
+        if let Some(timestamp) = self.log_timestamp {
+            cfg.log.timestamp = timestamp;
+        }
+        if self.log_per_swarm {
+            cfg.log.per_swarm = true;
+        }
+        if self.log_blink_fields {
+            cfg.log.blink_fields = true;
+        }
+        if self.dedup_log {
+            cfg.log.dedup = true;
+        }
+        if let Some(addr) = self.broadcast_addr {
+            cfg.network.broadcast_addr = addr;
+        }
+        if let Some(group) = self.multicast {
+            cfg.network.multicast_group = Some(group);
+        }
+        if self.ack {
+            cfg.network.udp_ack = true;
+        }
+        if let Some(secret) = &self.secret {
+            cfg.network.secret = Some(secret.clone());
+        }
+        if let Some(mapping) = self.mapping {
+            cfg.blink.mapping = mapping;
+        }
+        if let Some(window) = self.smoothing {
+            cfg.blink.smoothing_window = window.get();
+        }
+        if let Some(ms) = self.blink_min_ms {
+            cfg.blink.min_ms = ms;
+        }
+        if let Some(ms) = self.blink_max_ms {
+            cfg.blink.max_ms = Some(ms);
+        }
+        if let Some(rgb) = self.rgb {
+            cfg.gpio.rgb = rgb;
+        }
+        if let Some(pwm) = self.pwm {
+            cfg.gpio.pwm = pwm;
+        }
+        if let Some(leds) = self.leds_on_shutdown {
+            cfg.gpio.leds_on_shutdown = leds;
+        }
+        if let Some(output) = self.output {
+            cfg.status.output = output;
+        }
+        if let Some(ms) = self.status_ms {
+            cfg.status.print_ms = ms;
+        }
+        if let Some(ms) = self.heartbeat_ms {
+            cfg.status.heartbeat_ms = ms;
+        }
+        if self.quiet {
+            cfg.status.print_ms = 0;
+        }
+        if self.simulate {
+            cfg.gpio.simulate = true;
+        }
+        if self.single_master {
+            cfg.master.single = true;
+        }
+        if let Some(path) = &self.gpiochip {
+            cfg.gpio.chip = Some(path.clone());
+        }
+        if let Some(low) = self.alarm_low {
+            cfg.alarm.low = Some(low);
+        }
+        if let Some(high) = self.alarm_high {
+            cfg.alarm.high = Some(high);
+        }
+        if let Some(limit) = self.stuck_limit {
+            cfg.reading.stuck_limit = limit;
+        }
+        if self.stuck_dark {
+            cfg.reading.stuck_dark = true;
+        }
+        if let Some(batch) = self.batch_reading {
+            cfg.reading.batch = batch;
+        }
+        if self.no_selftest {
+            cfg.gpio.selftest = false;
+        }
+        if self.replay.is_some() {
+            // replay binds no sockets: network inputs and outputs stay off
+            cfg.network.tcp_ingest_port = None;
+            cfg.network.metrics_port = None;
+            cfg.network.web_port = None;
+            cfg.network.grpc_port = None;
+            cfg.network.influx_udp = None;
+            cfg.network.forward_to = None;
+            cfg.mqtt.host = None;
+            // saved frames are +++...*** text, whatever the live wire format
+            cfg.network.protocol = WireProtocol::Text;
+        }
+    }
+}
@@ -0,0 +1,1195 @@
+use crate::blink::MIN_BLINK_SECONDS;
+use crate::protocol::{Markers, RPI_END, RPI_START};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+// Loaded from this file in the working directory when present.
+pub const CONFIG_FILE: &str = "lightswarm.toml";
+
+// ===== Defaults (used for any key missing from the file) =====
+const BUTTON_PIN: u32 = 26;
+const BUTTON_DEBOUNCE_MS: u64 = 200;
+const LONG_PRESS_MS: u64 = 2000;
+const RESET_COOLDOWN_MS: u64 = 500;
+// Longest gpio.reset_cooldown_ms accepted: readings are dropped for all of it, and one too far
+// off can't be represented as a deadline.
+const RESET_COOLDOWN_MAX_MS: u64 = 60_000;
+const CHIP_OPEN_ATTEMPTS: u32 = 5;
+const CHIP_RETRY_MS: u64 = 500;
+const WHITE_LED_PIN: u32 = 18;
+const RGB_LED_PINS: [u32; 3] = [17, 22, 27];
+const PWM_MIN_DUTY: u8 = 10;
+
+const PORT: u16 = 4210;
+const MAX_DATAGRAM_BYTES: usize = 1024;
+const READ_TIMEOUT_MS: u64 = 100;
+const BROADCAST_ADDR: Ipv4Addr = Ipv4Addr::BROADCAST;
+// IPv6 has no broadcast: resets go to the link-local all-nodes group unless multicast_group is set.
+pub const IPV6_ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+const LOG_PATH: &str = "sensor_readings.txt";
+const LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const LOG_FLUSH_MS: u64 = 1000;
+const LOG_BUFFER_BYTES: usize = 16 * 1024;
+const LOG_SYNC_MS: u64 = 0;
+const LOG_ROTATE_KEEP: usize = 5;
+
+// Blink mapping (same mapping as your ESP)
+const X1: f64 = 24.0;
+const Y1: f64 = 2010.0 / 1000.0;
+const X2: f64 = 1024.0;
+const Y2: f64 = 10.0 / 1000.0;
+
+const STATUS_PRINT_MS: u64 = 1000;
+const STATUS_JITTER_MS: u64 = 50;
+const STATUS_ROSTER_MS: u64 = 10000;
+const STATUS_DIAGNOSTICS_MS: u64 = 60000;
+const STATUS_HEARTBEAT_MS: u64 = 60000;
+
+const MASTER_TIMEOUT_MS: u64 = 5000;
+const MASTER_CONFIRM_PACKETS: u32 = 1;
+const MASTER_CONFIRM_MS: u64 = 0;
+
+const FLAP_THRESHOLD: usize = 4;
+const FLAP_WINDOW_MS: u64 = 5000;
+const FLAP_HOLD_MS: u64 = 10000;
+// Longest flap.window_ms / flap.hold_ms accepted (a day): a longer hold is as good as pinning
+// the Master, and one too far off can't be represented as a deadline.
+const FLAP_MAX_MS: u64 = 24 * 3600 * 1000;
+
+const READING_MIN: f64 = 0.0;
+const READING_MAX: f64 = 1024.0;
+const READING_STUCK_LIMIT: u32 = 50;
+
+const HISTORY_CAPACITY: usize = 256;
+
+const ALARM_HYSTERESIS: u32 = 20;
+
+const MQTT_PORT: u16 = 1883;
+const MQTT_TOPIC_PREFIX: &str = "lightswarm";
+const MQTT_CLIENT_ID: &str = "lightswarm-pi";
+
+const INVENTORY_REPORT_MS: u64 = 30000;
+const INVENTORY_JITTER_MS: u64 = 500;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub gpio: GpioConfig,
+    pub network: NetworkConfig,
+    pub blink: BlinkConfig,
+    pub status: StatusConfig,
+    pub master: MasterConfig,
+    pub flap: FlapConfig,
+    pub log: LogConfig,
+    pub reading: ReadingConfig,
+    pub alarm: AlarmConfig,
+    pub history: HistoryConfig,
+    pub mqtt: MqttConfig,
+    pub inventory: InventoryConfig,
+}
+
+// [gpio] BCM pin numbers
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpioConfig {
+    pub button_pin: u32,
+    // Button reads 0 while pressed (wired to GND); false for a button pulling the pin high.
+    pub button_active_low: bool,
+    // After an accepted press, further presses are ignored for this long (contact bounce).
+    pub button_debounce_ms: u64,
+    // Held at least this long: hard reset (log cleared, swarm reset); shorter: state only.
+    pub long_press_ms: u64,
+    // After a reset, received packets are dropped for this long: readings still in flight from
+    // before the reset would otherwise repopulate the fresh state.
+    pub reset_cooldown_ms: u64,
+    pub white_led_pin: u32,
+    pub rgb_led_pins: Vec<u32>,
+    // Swarm id -> LED index pinned here always gets that LED, ahead of arrival-order assignment;
+    // other swarms are assigned the remaining LEDs (at most one id per LED).
+    pub led_assignments: HashMap<String, usize>,
+    // JSON file keeping the swarm id -> LED assignments across restarts and resets (unset = off).
+    pub led_map_file: Option<PathBuf>,
+    // Single: one LED per swarm, only the Master's blinks. Color: the three pins (red, green,
+    // blue) drive one RGB LED whose color follows the reading. Swarm: that LED shows the
+    // Master's own color.
+    pub rgb: RgbMode,
+    // Swarm id -> "#rrggbb" in swarm color mode; other swarms get distinct colors of their own.
+    pub swarm_colors: HashMap<String, RgbColor>,
+    // LEDs light when their line is driven low (e.g. through an inverting transistor).
+    pub led_active_low: bool,
+    // Software PWM: the Master's LED glows brighter for higher readings while it blinks.
+    pub pwm: PwmMode,
+    // Duty cycle (percent) at the darkest reading, so the LED stays visible.
+    pub pwm_min_duty: u8,
+    // Run without GPIO hardware: LED writes are printed, the button is never pressed.
+    pub simulate: bool,
+    // The gpiochip device to use (unset = /dev/gpiochip4, falling back to /dev/gpiochip0).
+    pub chip: Option<PathBuf>,
+    // The gpiochip may show up shortly after startup (or a driver reload): opening it is tried
+    // this many times, waiting chip_retry_ms in between and doubling the wait each time.
+    pub chip_open_attempts: u32,
+    pub chip_retry_ms: u64,
+    // Light each LED in turn at startup to check the wiring.
+    pub selftest: bool,
+    // What the LEDs show once the program stops: all off, or left at their last state (not
+    // honoured on Pi 1-4, see ShutdownLeds::Hold).
+    pub leds_on_shutdown: ShutdownLeds,
+}
+
+impl Default for GpioConfig {
+    fn default() -> Self {
+        Self {
+            button_pin: BUTTON_PIN,
+            button_active_low: true,
+            button_debounce_ms: BUTTON_DEBOUNCE_MS,
+            long_press_ms: LONG_PRESS_MS,
+            reset_cooldown_ms: RESET_COOLDOWN_MS,
+            white_led_pin: WHITE_LED_PIN,
+            rgb_led_pins: RGB_LED_PINS.to_vec(),
+            led_assignments: HashMap::new(),
+            led_map_file: None,
+            rgb: RgbMode::Single,
+            swarm_colors: HashMap::new(),
+            led_active_low: false,
+            pwm: PwmMode::On,
+            pwm_min_duty: PWM_MIN_DUTY,
+            simulate: false,
+            chip: None,
+            chip_open_attempts: CHIP_OPEN_ATTEMPTS,
+            chip_retry_ms: CHIP_RETRY_MS,
+            selftest: true,
+            leds_on_shutdown: ShutdownLeds::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PwmMode {
+    #[default]
+    On,
+    // plain on/off blinking, for pins that can't be pulsed fast enough
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownLeds {
+    #[default]
+    Off,
+    // skip the final switch-off; the lines are still freed at exit, which on Pi 1-4 turns the
+    // pins to inputs and the LEDs dark, so this only freezes them where the driver keeps a
+    // freed line's level
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RgbMode {
+    #[default]
+    Single,
+    // red at the darkest reading (blink.x1), green at the brightest (blink.x2)
+    Color,
+    // the Master's color: gpio.swarm_colors, or else one assigned at its first packet
+    Swarm,
+}
+
+// An RGB LED color, written "#rrggbb" in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RgbColor(pub [u8; 3]);
+
+impl RgbColor {
+    // Per-channel duty in percent (red, green, blue), as the GPIO thread drives them.
+    pub fn duty(self) -> [u8; 3] {
+        self.0.map(|c| (u32::from(c) * 100 / 255) as u8)
+    }
+}
+
+impl TryFrom<String> for RgbColor {
+    type Error = String;
+
+    fn try_from(text: String) -> std::result::Result<Self, String> {
+        let hex = text.strip_prefix('#').unwrap_or(&text);
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(RgbColor([r, g, b])),
+            _ => Err(format!("invalid color {text:?}, expected \"#rrggbb\"")),
+        }
+    }
+}
+
+impl From<RgbColor> for String {
+    fn from(color: RgbColor) -> String {
+        let [r, g, b] = color.0;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+// [network]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub port: u16,
+    // Wire format of received readings; status queries, resets and everything the Pi sends
+    // stay text frames.
+    pub protocol: WireProtocol,
+    // Markers around every frame, received and sent (RESET_REQUESTED, ACKs, relayed readings);
+    // for swarms whose data may contain the default +++ or ***.
+    pub start_marker: String,
+    pub end_marker: String,
+    // Largest accepted UDP payload; bigger datagrams are dropped with a warning.
+    pub max_datagram_bytes: usize,
+    // Longest wait for a datagram before the receive loop runs its housekeeping (TCP frames,
+    // periodic reports, signals, shutdown); longer saves idle CPU but delays those.
+    pub read_timeout_ms: u64,
+    // Address family of the UDP socket; IPv4 resets are broadcast, IPv6 resets multicast.
+    pub family: AddressFamily,
+    // Local address the UDP socket binds to (unset = every interface, 0.0.0.0 or ::); resets
+    // are sent from the same socket, so they leave through the same interface.
+    pub bind_addr: Option<IpAddr>,
+    // Destination of the RESET_REQUESTED broadcast (IPv4 only).
+    pub broadcast_addr: Ipv4Addr,
+    // Join this multicast group (e.g. 239.1.2.3, or ff02::/16 for IPv6) and send resets to it
+    // instead of broadcast_addr, for networks that filter broadcasts.
+    pub multicast_group: Option<IpAddr>,
+    // Interface (e.g. "wlan0") for IPv6 link-local addresses and multicast (unset = default).
+    pub interface: Option<String>,
+    // Accept +++Forward,<origin_pi>,<swarm_id>,<reading>*** frames from other Pis and
+    // track them under the namespaced key "<origin_pi>/<swarm_id>".
+    pub aggregator_mode: bool,
+    // Same +++...*** frames over TCP, fed into the same pipeline as UDP packets.
+    pub tcp_ingest_port: Option<u16>,
+    // Prometheus metrics over HTTP on /metrics.
+    pub metrics_port: Option<u16>,
+    // Live page and Server-Sent Events stream of the console events over HTTP.
+    pub web_port: Option<u16>,
+    // gRPC stream of the console events (needs the "grpc" cargo feature).
+    pub grpc_port: Option<u16>,
+    // InfluxDB UDP listener that gets one line-protocol point per reading.
+    pub influx_udp: Option<SocketAddr>,
+    // Relay every accepted reading to this host (e.g. a central aggregator Pi), best-effort,
+    // from the receive socket. Readings that came from that host are not sent back.
+    pub forward_to: Option<SocketAddr>,
+    // Re-frame relayed readings as +++Forward,<origin>,<id>,<reading>*** with this origin name,
+    // for a target in aggregator_mode (unset = plain +++Master,<id>,<reading>***).
+    pub forward_origin: Option<String>,
+    // Reply +++ACK,<swarm_id>,<reading>*** on the connection for every accepted frame.
+    pub tcp_ack: bool,
+    // Send the same ACK datagram back to the sender of every logged UDP reading.
+    pub udp_ack: bool,
+    // Shared HMAC-SHA256 secret: frames must end in ",<hex_tag>" and resets are signed.
+    pub secret: Option<String>,
+    // Only these swarm ids are accepted (empty = all); forwarded ids as "<origin_pi>/<id>".
+    #[serde(serialize_with = "sorted")]
+    pub allowed_swarm_ids: HashSet<String>,
+    // Longest swarm id accepted, in characters (0 = any length), and the characters allowed in
+    // it; ids with control characters or path separators are always rejected.
+    pub swarm_id_max_len: usize,
+    pub swarm_id_charset: SwarmIdCharset,
+    // Accept +++RESET_REQUESTED*** over UDP as a long button press (log, state and swarm reset),
+    // from reset_allowed_ips only (empty = any sender, which requires `secret`).
+    pub remote_reset: bool,
+    #[serde(serialize_with = "sorted")]
+    pub reset_allowed_ips: HashSet<IpAddr>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            port: PORT,
+            protocol: WireProtocol::Text,
+            start_marker: RPI_START.to_string(),
+            end_marker: RPI_END.to_string(),
+            max_datagram_bytes: MAX_DATAGRAM_BYTES,
+            read_timeout_ms: READ_TIMEOUT_MS,
+            family: AddressFamily::Ipv4,
+            bind_addr: None,
+            broadcast_addr: BROADCAST_ADDR,
+            multicast_group: None,
+            interface: None,
+            aggregator_mode: false,
+            tcp_ingest_port: None,
+            metrics_port: None,
+            web_port: None,
+            grpc_port: None,
+            influx_udp: None,
+            forward_to: None,
+            forward_origin: None,
+            tcp_ack: false,
+            udp_ack: false,
+            secret: None,
+            allowed_swarm_ids: HashSet::new(),
+            swarm_id_max_len: 0,
+            swarm_id_charset: SwarmIdCharset::Any,
+            remote_reset: false,
+            reset_allowed_ips: HashSet::new(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn markers(&self) -> Markers<'_> {
+        Markers {
+            start: &self.start_marker,
+            end: &self.end_marker,
+        }
+    }
+
+    // Every interface of the configured family (0.0.0.0 or ::).
+    pub fn any_ip(&self) -> IpAddr {
+        match self.family {
+            AddressFamily::Ipv4 => Ipv4Addr::UNSPECIFIED.into(),
+            AddressFamily::Ipv6 => Ipv6Addr::UNSPECIFIED.into(),
+        }
+    }
+
+    // The address the UDP socket binds to: bind_addr, else every interface.
+    pub fn bind_ip(&self) -> IpAddr {
+        self.bind_addr.unwrap_or_else(|| self.any_ip())
+    }
+
+    // Where RESET_REQUESTED is sent: the multicast group when set, else broadcast_addr
+    // (IPv4) or the all-nodes group (IPv6).
+    pub fn reset_addr(&self) -> IpAddr {
+        self.multicast_group.unwrap_or(match self.family {
+            AddressFamily::Ipv4 => self.broadcast_addr.into(),
+            AddressFamily::Ipv6 => IPV6_ALL_NODES.into(),
+        })
+    }
+
+    pub fn reset_allowed_from(&self, ip: IpAddr) -> bool {
+        self.reset_allowed_ips.is_empty() || self.reset_allowed_ips.contains(&ip)
+    }
+
+    // swarm_id_max_len and swarm_id_charset, applied to the origin and the id of forwarded ids.
+    pub fn swarm_id_allowed(&self, key: &str) -> bool {
+        key.split('/').all(|id| {
+            (self.swarm_id_max_len == 0 || id.chars().count() <= self.swarm_id_max_len)
+                && id.chars().all(|c| self.swarm_id_charset.allows(c))
+        })
+    }
+}
+
+// [blink] mapping endpoints: x = reading, y = blink interval in seconds
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlinkConfig {
+    // Curve through the two endpoints.
+    pub mapping: BlinkCurve,
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+    // The interval follows the mean of the Master's latest readings (1 = no smoothing);
+    // logged readings stay raw.
+    pub smoothing_window: usize,
+    // Floor and optional cap of the interval in ms, whatever the mapping gives (like the
+    // set-blink-bounds control command).
+    pub min_ms: f64,
+    pub max_ms: Option<f64>,
+}
+
+impl Default for BlinkConfig {
+    fn default() -> Self {
+        Self {
+            mapping: BlinkCurve::Linear,
+            x1: X1,
+            y1: Y1,
+            x2: X2,
+            y2: Y2,
+            smoothing_window: 1,
+            min_ms: MIN_BLINK_SECONDS * 1000.0,
+            max_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BlinkCurve {
+    // interval changes evenly with the reading
+    #[default]
+    Linear,
+    // interval changes evenly with ln(1 + reading), for sensors with a logarithmic response
+    Log,
+}
+
+// [status] terminal output and STATUS line rate
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusConfig {
+    pub output: OutputFormat,
+    // Interval between STATUS lines; 0 disables them (EVENT lines are unaffected).
+    pub print_ms: u64,
+    // Interval between roster lines (every swarm heard from, seconds since its last packet);
+    // 0 disables them.
+    pub roster_ms: u64,
+    // Interval between diagnostics lines (frames dropped while decoding, by cause), printed
+    // only when the counts changed; 0 disables them.
+    pub diagnostics_ms: u64,
+    // Interval between heartbeat lines, printed by the blink timer whatever the traffic so a
+    // quiet network can be told apart from a hung program; 0 disables them.
+    pub heartbeat_ms: u64,
+    // Each periodic deadline is pushed out by a random 0..=jitter_ms so tasks don't fire together.
+    pub jitter_ms: u64,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            output: OutputFormat::Text,
+            print_ms: STATUS_PRINT_MS,
+            roster_ms: STATUS_ROSTER_MS,
+            diagnostics_ms: STATUS_DIAGNOSTICS_MS,
+            heartbeat_ms: STATUS_HEARTBEAT_MS,
+            jitter_ms: STATUS_JITTER_MS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    // [1234] EVENT master_set  to=abc  ...
+    #[default]
+    Text,
+    // one JSON object per event on stdout; other messages move to stderr
+    Json,
+}
+
+// [master]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MasterConfig {
+    // Silence after which the current Master is dropped and its LED turned off (0 = never).
+    pub timeout_ms: u64,
+    // Hysteresis: a new swarm takes over only after this many consecutive packets,
+    // spread over at least confirm_ms (defaults: switch on the first packet).
+    pub confirm_packets: u32,
+    pub confirm_ms: u64,
+    // Only the current Master's LED blinks. Otherwise every swarm sending Master readings
+    // blinks its own LED at its own rate until it has been silent for timeout_ms.
+    pub single: bool,
+}
+
+impl Default for MasterConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: MASTER_TIMEOUT_MS,
+            confirm_packets: MASTER_CONFIRM_PACKETS,
+            confirm_ms: MASTER_CONFIRM_MS,
+            single: false,
+        }
+    }
+}
+
+// [flap] `threshold` master changes within `window_ms` enter stabilization mode, which
+// holds the current master for `hold_ms` (extended while flapping persists).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlapConfig {
+    pub threshold: usize,
+    pub window_ms: u64,
+    pub hold_ms: u64,
+}
+
+impl Default for FlapConfig {
+    fn default() -> Self {
+        Self {
+            threshold: FLAP_THRESHOLD,
+            window_ms: FLAP_WINDOW_MS,
+            hold_ms: FLAP_HOLD_MS,
+        }
+    }
+}
+
+// [log] sensor log file and its optional extra fields
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    // Prefix each record with a sequence number ("#<n> ", in CSV a seq column), counting from 0
+    // at startup and whenever the log is cleared; rotation and soft resets keep counting.
+    pub record_seq: bool,
+    // Append the exact accepted payload to each record (roughly doubles log size).
+    pub raw_payload: RawPayloadLog,
+    // Add the blink interval mapped from the reading and the swarm's LED to each record
+    // (text: " blink_ms=<ms> led=<n>", CSV: blink_ms,led_index columns).
+    pub blink_fields: bool,
+    // Wall-clock (UTC) prefix of each record.
+    pub timestamp: LogTimestamp,
+    // Rotate once the file reaches this size (0 = never): <stem>.1<ext> is the newest
+    // rotated file, and at most `rotate_keep` are kept.
+    pub max_bytes: u64,
+    pub rotate_keep: usize,
+    // A SIGHUP reload also clears the log, like a long button press.
+    pub truncate_on_reload: bool,
+    // Write each swarm's records to its own sensor_<id> file next to `path` instead.
+    pub per_swarm: bool,
+    // Hold back a text record while the next ones repeat it (same swarm, reading and fields),
+    // then write it once with a " (xN)" count; written on a change, flush_ms after the first
+    // of the run, or on a reset or shutdown.
+    pub dedup: bool,
+    // Records are buffered and written in batches: at most `flush_ms` after the first buffered
+    // one, or as soon as `buffer_bytes` are waiting for a file (0 = write every record).
+    pub flush_ms: u64,
+    pub buffer_bytes: usize,
+    // fsync (sync_data) written lines at most this long after they were written (0 = leave it
+    // to the OS), so a power loss can't take more than flush_ms + sync_ms of records.
+    pub sync_ms: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(LOG_PATH),
+            format: LogFormat::Text,
+            record_seq: false,
+            raw_payload: RawPayloadLog::Off,
+            blink_fields: false,
+            timestamp: LogTimestamp::Rfc3339,
+            max_bytes: LOG_MAX_BYTES,
+            rotate_keep: LOG_ROTATE_KEEP,
+            truncate_on_reload: false,
+            per_swarm: false,
+            dedup: false,
+            flush_ms: LOG_FLUSH_MS,
+            buffer_bytes: LOG_BUFFER_BYTES,
+            sync_ms: LOG_SYNC_MS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WireProtocol {
+    // +++...*** frames
+    #[default]
+    Text,
+    // fixed-size frames decoded by protocol::parse_binary
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    #[default]
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwarmIdCharset {
+    // anything but control characters and path separators
+    #[default]
+    Any,
+    // ASCII letters, digits, '-' and '_' (also safe as a per-swarm log file name)
+    Alphanumeric,
+}
+
+impl SwarmIdCharset {
+    pub fn allows(self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Alphanumeric => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // <timestamp> Swarm ID <id>: <reading>
+    #[default]
+    Text,
+    // timestamp,swarm_id,reading,sender_ip[,seq][,blink_ms,led_index] (raw_payload is
+    // text-only)
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawPayloadLog {
+    #[default]
+    Off,
+    // raw="+++Master,abc,512***" (Rust string escaping)
+    Escaped,
+    // raw=hex:2b2b2b...
+    Hex,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTimestamp {
+    // 2024-01-02T03:04:05.123Z
+    #[default]
+    Rfc3339,
+    // 1704164645123
+    EpochMs,
+}
+
+// [reading] valid sensor range; readings outside [min, max] are warned about
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadingConfig {
+    pub min: f64,
+    pub max: f64,
+    pub out_of_range: OutOfRange,
+    // More than this many identical readings in a row from one swarm: sensor_stuck (0 = off).
+    pub stuck_limit: u32,
+    // Stop blinking a stuck Master's LED until its reading changes.
+    pub stuck_dark: bool,
+    // Which value of a batched frame drives the blink timing; every sample is logged.
+    pub batch: BatchReading,
+}
+
+impl Default for ReadingConfig {
+    fn default() -> Self {
+        Self {
+            min: READING_MIN,
+            max: READING_MAX,
+            out_of_range: OutOfRange::Skip,
+            stuck_limit: READING_STUCK_LIMIT,
+            stuck_dark: false,
+            batch: BatchReading::Last,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchReading {
+    // the newest sample
+    #[default]
+    Last,
+    // the mean of the frame's samples
+    Average,
+}
+
+impl BatchReading {
+    // One value for a frame's (non-empty) readings
+    pub fn combine(self, readings: &[f64]) -> f64 {
+        match self {
+            BatchReading::Last => readings[readings.len() - 1],
+            BatchReading::Average => readings.iter().sum::<f64>() / readings.len() as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfRange {
+    // dropped: not logged and never drives the Master LED
+    #[default]
+    Skip,
+    // processed as usual, with the log record marked out_of_range (text format)
+    Flag,
+}
+
+// [alarm] the white LED lights and a threshold_alarm event is printed when the Master's
+// reading reaches a bound
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlarmConfig {
+    // Alarm at or below `low` / at or above `high` (unset = off).
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    // An alarm only ends once the reading is back inside its bound by this much.
+    pub hysteresis: u32,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            low: None,
+            high: None,
+            hysteresis: ALARM_HYSTERESIS,
+        }
+    }
+}
+
+// [history] recent readings kept in memory per swarm id, oldest dropped first (0 = off)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub capacity: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: HISTORY_CAPACITY,
+        }
+    }
+}
+
+// [mqtt] publish readings to a broker (dormant without a host)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    // Topics: <prefix>/<swarm_id>/reading and <prefix>/master (retained)
+    pub topic_prefix: String,
+    pub client_id: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: MQTT_PORT,
+            topic_prefix: MQTT_TOPIC_PREFIX.to_string(),
+            client_id: MQTT_CLIENT_ID.to_string(),
+        }
+    }
+}
+
+// [inventory]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InventoryConfig {
+    // Swarm ids expected in this installation (empty = no inventory checks).
+    pub expected_swarm_ids: Vec<String>,
+    // Drop packets from ids outside the list instead of only warning.
+    pub drop_unknown: bool,
+    // How often expected ids that have not reported are listed.
+    pub report_ms: u64,
+    pub jitter_ms: u64,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        Self {
+            expected_swarm_ids: Vec::new(),
+            drop_unknown: false,
+            report_ms: INVENTORY_REPORT_MS,
+            jitter_ms: INVENTORY_JITTER_MS,
+        }
+    }
+}
+
+impl Config {
+    // Missing file -> defaults; a present but invalid file is an error.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        if config.gpio.rgb_led_pins.is_empty() {
+            bail!("{}: gpio.rgb_led_pins is empty", path.display());
+        }
+        let leds = config.gpio.rgb_led_pins.len();
+        if let Some((id, idx)) = config.gpio.led_assignments.iter().find(|(_, &i)| i >= leds) {
+            let path = path.display();
+            bail!("{path}: gpio.led_assignments: {id} = {idx}, but there are only {leds} LEDs");
+        }
+        let assignments = &config.gpio.led_assignments;
+        let mut pinned: Vec<_> = assignments.iter().map(|(id, &i)| (i, id)).collect();
+        pinned.sort();
+        if let Some([(idx, a), (_, b)]) = pinned.windows(2).find(|w| w[0].0 == w[1].0) {
+            let path = path.display();
+            bail!("{path}: gpio.led_assignments: {a} and {b} are both pinned to LED{idx}");
+        }
+        if config.network.max_datagram_bytes == 0 {
+            bail!("{}: network.max_datagram_bytes is 0", path.display());
+        }
+        if config.reading.min > config.reading.max {
+            bail!("{}: reading.min is above reading.max", path.display());
+        }
+        if config.blink.smoothing_window == 0 {
+            bail!("{}: blink.smoothing_window is 0", path.display());
+        }
+        Ok(Some(config))
+    }
+
+    // Several Masters blink at once: not with master.single, nor with one RGB LED (color mode).
+    pub fn multi_master(&self) -> bool {
+        !self.master.single && self.gpio.rgb == RgbMode::Single
+    }
+
+    // Checks that also cover command-line overrides, run once the flags are applied.
+    pub fn validate(&self) -> Result<()> {
+        if self.gpio.rgb != RgbMode::Single && self.gpio.rgb_led_pins.len() != 3 {
+            bail!("RGB color mode needs exactly 3 gpio.rgb_led_pins (red, green, blue)");
+        }
+        if self.gpio.reset_cooldown_ms > RESET_COOLDOWN_MAX_MS {
+            bail!(
+                "gpio.reset_cooldown_ms ({}) must be at most {RESET_COOLDOWN_MAX_MS}",
+                self.gpio.reset_cooldown_ms
+            );
+        }
+        let blink = &self.blink;
+        if blink.min_ms.is_nan() || blink.min_ms <= 0.0 {
+            bail!("blink.min_ms ({}) must be above 0", blink.min_ms);
+        }
+        if let Some(max_ms) = blink.max_ms {
+            if max_ms.is_nan() || blink.min_ms > max_ms {
+                bail!(
+                    "blink.min_ms ({}) must not be above blink.max_ms ({max_ms})",
+                    blink.min_ms
+                );
+            }
+        }
+        if let (Some(low), Some(high)) = (self.alarm.low, self.alarm.high) {
+            if low >= high {
+                bail!("alarm.low ({low}) must be below alarm.high ({high})");
+            }
+        }
+        let flap = &self.flap;
+        if flap.threshold < 2 {
+            // one change is no flapping, and 0 would never let the hold end
+            bail!("flap.threshold ({}) must be at least 2", flap.threshold);
+        }
+        for (key, ms) in [("window_ms", flap.window_ms), ("hold_ms", flap.hold_ms)] {
+            if ms == 0 || ms > FLAP_MAX_MS {
+                bail!("flap.{key} ({ms}) must be between 1 and {FLAP_MAX_MS}");
+            }
+        }
+        let net = &self.network;
+        if net.grpc_port.is_some() && !cfg!(feature = "grpc") {
+            bail!("network.grpc_port needs a build with the \"grpc\" cargo feature");
+        }
+        if net.read_timeout_ms == 0 {
+            // a zero timeout would make the receive loop spin
+            bail!("network.read_timeout_ms must be at least 1");
+        }
+        let ipv6 = net.family == AddressFamily::Ipv6;
+        if let Some(addr) = net.bind_addr.filter(|a| a.is_ipv6() != ipv6) {
+            bail!("network.bind_addr {addr} does not match network.family");
+        }
+        if let Some(group) = net.multicast_group {
+            if group.is_ipv6() != ipv6 {
+                bail!("network.multicast_group {group} does not match network.family");
+            }
+            if !group.is_multicast() {
+                bail!("{group} is not a multicast address (224.0.0.0/4 or ff00::/8)");
+            }
+        }
+        if net.start_marker.is_empty() || net.end_marker.is_empty() {
+            bail!("network.start_marker and network.end_marker must not be empty");
+        }
+        if net.start_marker == net.end_marker {
+            bail!(
+                "network.start_marker and network.end_marker are both {:?}; they must differ",
+                net.start_marker
+            );
+        }
+        if net.protocol == WireProtocol::Binary {
+            // both only exist for text frames
+            if net.secret.is_some() {
+                bail!("network.secret needs network.protocol = \"text\"");
+            }
+            if net.tcp_ingest_port.is_some() {
+                bail!("network.tcp_ingest_port needs network.protocol = \"text\"");
+            }
+        }
+        if net.remote_reset && net.reset_allowed_ips.is_empty() && net.secret.is_none() {
+            bail!("network.remote_reset needs network.reset_allowed_ips or network.secret");
+        }
+        Ok(())
+    }
+
+    // SIGHUP reload: this config with the blink mapping, alarm thresholds, status rates and
+    // log.truncate_on_reload taken from `new`, plus every other changed setting ("section.key"),
+    // which keeps its current value until a restart.
+    pub fn reloaded(&self, new: Config) -> (Config, Vec<String>) {
+        let restart = changed_settings(self, &new)
+            .into_iter()
+            .filter(|key| {
+                !RELOADABLE
+                    .iter()
+                    .any(|r| key == r || key.starts_with(&format!("{r}.")))
+            })
+            .collect();
+        let mut cfg = self.clone();
+        cfg.blink = new.blink;
+        cfg.alarm = new.alarm;
+        cfg.status.print_ms = new.status.print_ms;
+        cfg.status.roster_ms = new.status.roster_ms;
+        cfg.status.diagnostics_ms = new.status.diagnostics_ms;
+        cfg.status.heartbeat_ms = new.status.heartbeat_ms;
+        cfg.status.jitter_ms = new.status.jitter_ms;
+        cfg.log.truncate_on_reload = new.log.truncate_on_reload;
+        (cfg, restart)
+    }
+}
+
+// What Config::reloaded applies: whole sections, or single "section.key" settings.
+const RELOADABLE: &[&str] = &[
+    "blink",
+    "alarm",
+    "status.print_ms",
+    "status.roster_ms",
+    "status.diagnostics_ms",
+    "status.heartbeat_ms",
+    "status.jitter_ms",
+    "log.truncate_on_reload",
+];
+
+// The "section.key" settings that differ between two configs, sorted; tables below that level
+// (e.g. gpio.led_assignments) count as one setting.
+fn changed_settings(old: &Config, new: &Config) -> Vec<String> {
+    let (old, new) = (settings(old), settings(new));
+    let mut changed = Vec::new();
+    for (section, keys) in &old {
+        for (key, value) in keys.as_object().into_iter().flatten() {
+            if new[section].get(key) != Some(value) {
+                changed.push(format!("{section}.{key}"));
+            }
+        }
+    }
+    changed.sort();
+    changed
+}
+
+// Sets are written sorted, so equal sets compare equal in changed_settings.
+fn sorted<T: Ord + Serialize, S: serde::Serializer>(
+    set: &HashSet<T>,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut items: Vec<&T> = set.iter().collect();
+    items.sort();
+    items.serialize(s)
+}
+
+// The config as sections of settings (every section and key is always present).
+fn settings(cfg: &Config) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(cfg) {
+        Ok(serde_json::Value::Object(sections)) => sections,
+        _ => unreachable!("the config serializes to a table"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_applies_only_reloadable_settings() {
+        let old = Config::default();
+        let new: Config = toml::from_str(
+            "[gpio]\nwhite_led_pin = 5\n[network]\nport = 4300\n\
+             [blink]\nx1 = 50.0\n[alarm]\nhigh = 900\n[status]\nprint_ms = 250\n\
+             [mqtt]\nport = 1884\n",
+        )
+        .unwrap();
+        let (cfg, restart) = old.reloaded(new);
+        assert_eq!(restart, ["gpio.white_led_pin", "mqtt.port", "network.port"]);
+        assert_eq!(cfg.blink.x1, 50.0);
+        assert_eq!(cfg.alarm.high, Some(900.0));
+        assert_eq!(cfg.status.print_ms, 250);
+        // the rest stays as it is until a restart
+        assert_eq!(cfg.gpio.white_led_pin, WHITE_LED_PIN);
+        assert_eq!(cfg.network.port, PORT);
+        assert_eq!(cfg.mqtt.port, MQTT_PORT);
+    }
+
+    #[test]
+    fn reload_reports_every_setting_it_skips() {
+        let old = Config::default();
+        let new: Config = toml::from_str(
+            "[log]\npath = \"other.txt\"\ntruncate_on_reload = true\n\
+             [reading]\nmax = 2000.0\n[flap]\nwindow_ms = 1\n\
+             [status]\ndiagnostics_ms = 5000\nheartbeat_ms = 7000\n\
+             [gpio.swarm_colors]\nabc = \"#0000ff\"\n",
+        )
+        .unwrap();
+        let (cfg, restart) = old.reloaded(new);
+        assert_eq!(
+            restart,
+            [
+                "flap.window_ms",
+                "gpio.swarm_colors",
+                "log.path",
+                "reading.max"
+            ]
+        );
+        assert_eq!(cfg.status.diagnostics_ms, 5000);
+        assert_eq!(cfg.status.heartbeat_ms, 7000);
+        assert!(cfg.log.truncate_on_reload);
+        assert_eq!(cfg.log.path, old.log.path);
+        // an unchanged file reports nothing, whatever order its sets come out in
+        let text = "[network]\nallowed_swarm_ids = [\"a\", \"b\", \"c\", \"d\", \"e\"]";
+        let old: Config = toml::from_str(text).unwrap();
+        for _ in 0..10 {
+            let new: Config = toml::from_str(text).unwrap();
+            assert!(old.reloaded(new).1.is_empty());
+        }
+    }
+
+    #[test]
+    fn validate_rejects_inverted_alarm_bounds() {
+        let mut cfg = Config::default();
+        assert!(cfg.validate().is_ok());
+        cfg.alarm.low = Some(900.0);
+        cfg.alarm.high = Some(100.0);
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_bounds_the_reset_cooldown() {
+        let mut cfg = Config::default();
+        cfg.gpio.reset_cooldown_ms = RESET_COOLDOWN_MAX_MS;
+        assert!(cfg.validate().is_ok());
+        cfg.gpio.reset_cooldown_ms = u64::MAX;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_flap_thresholds_that_never_end() {
+        let mut cfg = Config::default();
+        cfg.flap.threshold = 0;
+        assert!(cfg.validate().is_err());
+        cfg.flap.threshold = 1;
+        assert!(cfg.validate().is_err());
+        cfg.flap.threshold = 2;
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_bounds_flap_durations() {
+        let mut cfg = Config::default();
+        cfg.flap.hold_ms = u64::MAX;
+        assert!(cfg.validate().is_err());
+        cfg.flap.hold_ms = FLAP_MAX_MS;
+        assert!(cfg.validate().is_ok());
+        cfg.flap.window_ms = 0;
+        assert!(cfg.validate().is_err());
+        cfg.flap.window_ms = FLAP_MAX_MS + 1;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn ipv6_resets_are_multicast() {
+        let mut cfg: Config = toml::from_str("[network]\nfamily = \"ipv6\"").unwrap();
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.network.bind_ip(), IpAddr::from(Ipv6Addr::UNSPECIFIED));
+        assert_eq!(cfg.network.reset_addr(), IpAddr::from(IPV6_ALL_NODES));
+
+        cfg.network.multicast_group = Some("239.1.2.3".parse().unwrap());
+        assert!(cfg.validate().is_err());
+        cfg.network.multicast_group = Some("fe80::1".parse().unwrap());
+        assert!(cfg.validate().is_err());
+        cfg.network.multicast_group = Some("ff02::4210".parse().unwrap());
+        assert!(cfg.validate().is_ok());
+        cfg.network.bind_addr = Some("10.0.0.2".parse().unwrap());
+        assert!(cfg.validate().is_err());
+
+        // IPv4 stays the default
+        let cfg: Config = toml::from_str("[network]\nbind_addr = \"10.0.0.2\"").unwrap();
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.network.reset_addr(), IpAddr::from(Ipv4Addr::BROADCAST));
+    }
+
+    #[test]
+    fn remote_reset_needs_a_sender_check() {
+        let mut cfg = Config::default();
+        cfg.network.remote_reset = true;
+        assert!(cfg.validate().is_err());
+        cfg.network
+            .reset_allowed_ips
+            .insert("10.0.0.9".parse().unwrap());
+        assert!(cfg.validate().is_ok());
+        assert!(cfg.network.reset_allowed_from("10.0.0.9".parse().unwrap()));
+        assert!(!cfg.network.reset_allowed_from("10.0.0.5".parse().unwrap()));
+    }
+
+    #[test]
+    fn swarm_id_length_and_charset() {
+        let cfg: Config =
+            toml::from_str("[network]\nswarm_id_max_len = 4\nswarm_id_charset = \"alphanumeric\"")
+                .unwrap();
+        let allowed = |id| cfg.network.swarm_id_allowed(id);
+        assert!(allowed("ab-1"));
+        assert!(!allowed("abcde"));
+        assert!(!allowed("a b"));
+        assert!(!allowed("a.b"));
+        // forwarded ids: origin and id are checked separately
+        assert!(allowed("pi2/ab_1"));
+        assert!(!allowed("pi.2/ab"));
+        let any = Config::default().network;
+        assert!(any.swarm_id_allowed("a long id with spaces"));
+    }
+
+    #[test]
+    fn markers_must_be_set_and_distinct() {
+        let mut cfg: Config =
+            toml::from_str("[network]\nstart_marker = \"<<\"\nend_marker = \">>\"").unwrap();
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.network.markers().frame("x"), "<<x>>");
+        cfg.network.end_marker = "<<".to_string();
+        assert!(cfg.validate().is_err());
+        cfg.network.end_marker = String::new();
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn swarm_colors_are_hex_triplets() {
+        let cfg: Config = toml::from_str(
+            "[gpio]\nrgb = \"swarm\"\n[gpio.swarm_colors]\nabc = \"#0000ff\"\ndef = \"80FF00\"",
+        )
+        .unwrap();
+        assert_eq!(cfg.gpio.rgb, RgbMode::Swarm);
+        assert_eq!(cfg.gpio.swarm_colors["abc"], RgbColor([0, 0, 255]));
+        assert_eq!(cfg.gpio.swarm_colors["def"].duty(), [50, 100, 0]);
+        for bad in ["\"blue\"", "\"#00ff\"", "\"#00ffzz\"", "\"#00ff00ff\""] {
+            let text = format!("[gpio.swarm_colors]\nabc = {bad}");
+            assert!(toml::from_str::<Config>(&text).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn blink_floor_must_not_exceed_the_cap() {
+        let mut cfg: Config = toml::from_str("[blink]\nmin_ms = 50.0\nmax_ms = 800.0").unwrap();
+        assert!(cfg.validate().is_ok());
+        cfg.blink.max_ms = Some(20.0);
+        assert!(cfg.validate().is_err());
+        cfg.blink.max_ms = None;
+        cfg.blink.min_ms = 0.0;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn read_timeout_must_not_be_zero() {
+        let mut cfg = Config::default();
+        cfg.network.read_timeout_ms = 0;
+        assert!(cfg.validate().is_err());
+        cfg.network.read_timeout_ms = 1000;
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn binary_protocol_excludes_text_only_inputs() {
+        let mut cfg: Config = toml::from_str("[network]\nprotocol = \"binary\"").unwrap();
+        assert!(cfg.validate().is_ok());
+        cfg.network.tcp_ingest_port = Some(4211);
+        assert!(cfg.validate().is_err());
+        cfg.network.tcp_ingest_port = None;
+        cfg.network.secret = Some("s3cret".to_string());
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn batch_reading_picks_the_blink_value() {
+        let cfg: Config = toml::from_str("[reading]\nbatch = \"average\"").unwrap();
+        assert_eq!(cfg.reading.batch, BatchReading::Average);
+        assert_eq!(BatchReading::Average.combine(&[100.0, 200.0, 600.0]), 300.0);
+        assert_eq!(BatchReading::Last.combine(&[100.0, 200.0, 600.0]), 600.0);
+        assert_eq!(BatchReading::Average.combine(&[512.5]), 512.5);
+    }
+}
@@ -0,0 +1,541 @@
+// Console events: printed as text lines by default, or as newline-delimited JSON.
+
+use crate::protocol::display_swarm_id;
+use crate::state::SharedState;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    MasterSet {
+        ts_ms: u128,
+        swarm_id: String,
+        sender: IpAddr,
+        #[serde(serialize_with = "serialize_reading")]
+        reading: f64,
+        led_index: usize,
+        blink_ms: u64,
+    },
+    MasterChange {
+        ts_ms: u128,
+        from: String,
+        swarm_id: String,
+        sender: IpAddr,
+        #[serde(serialize_with = "serialize_reading")]
+        reading: f64,
+        led_index: usize,
+        blink_ms: u64,
+        // how long `from` was Master
+        master_dwell_ms: u64,
+    },
+    Status {
+        ts_ms: u128,
+        swarm_id: String,
+        sender: IpAddr,
+        #[serde(serialize_with = "serialize_reading")]
+        reading: f64,
+        led_index: usize,
+        blink_ms: u64,
+        // frames received in the last second, and seconds since start
+        pps: usize,
+        uptime_secs: u64,
+    },
+    Reset {
+        ts_ms: u128,
+        kind: ResetKind,
+    },
+    MasterTimeout {
+        ts_ms: u128,
+        swarm_id: String,
+        silent_ms: u64,
+    },
+    PacketLoss {
+        ts_ms: u128,
+        swarm_id: String,
+        seq: u64,
+        missing: u64,
+        lost_total: u64,
+    },
+    // one swarm has sent the same reading more than reading.stuck_limit times in a row
+    SensorStuck {
+        ts_ms: u128,
+        swarm_id: String,
+        #[serde(serialize_with = "serialize_reading")]
+        reading: f64,
+        repeats: u32,
+    },
+    // periodic list of every swarm heard from (status.roster_ms)
+    Roster {
+        ts_ms: u128,
+        swarms: Vec<RosterEntry>,
+    },
+    // the Master's reading reached an [alarm] bound
+    ThresholdAlarm {
+        ts_ms: u128,
+        swarm_id: String,
+        #[serde(serialize_with = "serialize_reading")]
+        reading: f64,
+        bound: AlarmBound,
+        #[serde(serialize_with = "serialize_reading")]
+        threshold: f64,
+    },
+    // periodic counts of frames dropped while decoding (status.diagnostics_ms), when changed
+    Diagnostics {
+        ts_ms: u128,
+        bad_utf8: u64,
+        bad_format: u64,
+        rejected_role: u64,
+    },
+    // periodic sign of life from the blink timer (status.heartbeat_ms), whatever the traffic
+    Heartbeat {
+        ts_ms: u128,
+        uptime_secs: u64,
+        packets_received: u64,
+        // the current Master, None when there is none
+        master: Option<String>,
+        bad_utf8: u64,
+        bad_format: u64,
+        rejected_role: u64,
+        rejected_unknown_swarm: u64,
+        log_records_dropped: u64,
+    },
+    // SIGHUP: the config file was re-read and the state reset
+    ConfigReload {
+        ts_ms: u128,
+        log_truncated: bool,
+    },
+    // the startup LED self-test has lit every LED once
+    SelftestDone {
+        ts_ms: u128,
+        leds: usize,
+    },
+    // no gpiochip could be opened yet; the next attempt follows after `retry_ms`
+    WaitingForGpiochip {
+        ts_ms: u128,
+        attempt: u32,
+        attempts: u32,
+        retry_ms: u64,
+    },
+    // the GPIO thread keeps failing; the supervisor retries after `retry_ms`
+    GpioFault {
+        ts_ms: u128,
+        failures: u32,
+        retry_ms: u64,
+        error: String,
+    },
+    // LED writes kept failing: the GPIO thread re-opened the chip and requested its lines again
+    GpioReconnect {
+        ts_ms: u128,
+        // consecutive failed writes, and the latest error
+        errors: u32,
+        error: String,
+    },
+    // the GPIO thread and its supervisor are gone: nothing drives the LEDs, so the program exits
+    GpioChannelClosed {
+        ts_ms: u128,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RosterEntry {
+    pub swarm_id: String,
+    pub last_seen_secs: u64,
+    // only swarms that have been Master have an LED
+    pub led_index: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResetKind {
+    // short press: state only
+    Soft,
+    // long press: broadcast RESET and clear the log
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlarmBound {
+    Low,
+    High,
+}
+
+impl fmt::Display for AlarmBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlarmBound::Low => "low",
+            AlarmBound::High => "high",
+        })
+    }
+}
+
+// Whole readings are written as JSON integers ("reading":512), as before readings could be
+// floats; others as numbers with a fraction.
+fn serialize_reading<S: Serializer>(reading: &f64, s: S) -> Result<S::Ok, S::Error> {
+    if reading.fract() == 0.0 && reading.abs() <= 9_007_199_254_740_992.0 {
+        s.serialize_i64(*reading as i64)
+    } else {
+        s.serialize_f64(*reading)
+    }
+}
+
+impl Event {
+    // One JSON object, without the trailing newline.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("events always serialize")
+    }
+}
+
+// The human-readable line, e.g. `[1234] EVENT master_set  to=abc  sender=10.0.0.5  LED0`.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::MasterSet {
+                ts_ms,
+                swarm_id,
+                sender,
+                led_index,
+                ..
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT master_set  to={}  sender={sender}  {}",
+                display_swarm_id(swarm_id),
+                SharedState::led_label(*led_index)
+            ),
+            Event::MasterChange {
+                ts_ms,
+                from,
+                swarm_id,
+                sender,
+                led_index,
+                master_dwell_ms,
+                ..
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT master_change  from={}  to={}  sender={sender}  {}  master_dwell={:.1}s",
+                display_swarm_id(from),
+                display_swarm_id(swarm_id),
+                SharedState::led_label(*led_index),
+                *master_dwell_ms as f64 / 1000.0
+            ),
+            Event::Status {
+                ts_ms,
+                swarm_id,
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+                pps,
+                uptime_secs,
+            } => write!(
+                f,
+                "[{ts_ms}] STATUS master={} sender={sender} value={reading} blink={blink_ms}ms {} pps={pps} uptime={uptime_secs}",
+                display_swarm_id(swarm_id),
+                SharedState::led_label(*led_index)
+            ),
+            Event::Reset {
+                ts_ms,
+                kind: ResetKind::Soft,
+            } => write!(f, "[{ts_ms}] EVENT reset_soft  white_led=3s"),
+            Event::Reset {
+                ts_ms,
+                kind: ResetKind::Hard,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT reset_hard  broadcast=RESET  log=cleared  white_led=3s"
+            ),
+            Event::MasterTimeout {
+                ts_ms,
+                swarm_id,
+                silent_ms,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT master_timeout  id={}  silent={silent_ms}ms",
+                display_swarm_id(swarm_id)
+            ),
+            Event::PacketLoss {
+                ts_ms,
+                swarm_id,
+                seq,
+                missing,
+                lost_total,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT packet_loss  id={}  missing={missing}  seq={seq}  lost={lost_total}",
+                display_swarm_id(swarm_id)
+            ),
+            Event::SensorStuck {
+                ts_ms,
+                swarm_id,
+                reading,
+                repeats,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT sensor_stuck  id={}  reading={reading}  repeats={repeats}",
+                display_swarm_id(swarm_id)
+            ),
+            Event::Roster { ts_ms, swarms } => {
+                write!(f, "[{ts_ms}] EVENT roster")?;
+                for entry in swarms {
+                    let id = display_swarm_id(&entry.swarm_id);
+                    write!(f, "  {id}={}s", entry.last_seen_secs)?;
+                    if let Some(idx) = entry.led_index {
+                        write!(f, "/{}", SharedState::led_label(idx))?;
+                    }
+                }
+                Ok(())
+            }
+            Event::Diagnostics {
+                ts_ms,
+                bad_utf8,
+                bad_format,
+                rejected_role,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT diagnostics  bad_utf8={bad_utf8}  bad_format={bad_format}  rejected_role={rejected_role}"
+            ),
+            Event::Heartbeat {
+                ts_ms,
+                uptime_secs,
+                packets_received,
+                master,
+                bad_utf8,
+                bad_format,
+                rejected_role,
+                rejected_unknown_swarm,
+                log_records_dropped,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT heartbeat  uptime={uptime_secs}s  packets={packets_received}  master={}  bad_utf8={bad_utf8}  bad_format={bad_format}  rejected_role={rejected_role}  rejected_unknown_swarm={rejected_unknown_swarm}  log_dropped={log_records_dropped}",
+                master.as_deref().map_or("none".into(), display_swarm_id)
+            ),
+            Event::ThresholdAlarm {
+                ts_ms,
+                swarm_id,
+                reading,
+                bound,
+                threshold,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT threshold_alarm  id={}  bound={bound}  reading={reading}  threshold={threshold}",
+                display_swarm_id(swarm_id)
+            ),
+            Event::ConfigReload {
+                ts_ms,
+                log_truncated,
+            } => {
+                let log = if *log_truncated { "truncated" } else { "kept" };
+                write!(f, "[{ts_ms}] EVENT config_reload  log={log}")
+            }
+            Event::SelftestDone { ts_ms, leds } => {
+                write!(f, "[{ts_ms}] EVENT selftest_done  leds={leds}")
+            }
+            Event::WaitingForGpiochip {
+                ts_ms,
+                attempt,
+                attempts,
+                retry_ms,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT waiting_for_gpiochip  attempt={attempt}/{attempts}  retry_in={retry_ms}ms"
+            ),
+            Event::GpioFault {
+                ts_ms,
+                failures,
+                retry_ms,
+                error,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT gpio_fault  failures={failures}  retry_in={retry_ms}ms  error={error}"
+            ),
+            Event::GpioReconnect {
+                ts_ms,
+                errors,
+                error,
+            } => write!(
+                f,
+                "[{ts_ms}] EVENT gpio_reconnect  errors={errors}  error={error}"
+            ),
+            Event::GpioChannelClosed { ts_ms } => {
+                write!(f, "[{ts_ms}] EVENT gpio_channel_closed  LEDs stopped, exiting")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Event {
+        Event::Status {
+            ts_ms: 1500,
+            swarm_id: "pi2/abc".to_string(),
+            sender: "10.0.0.5".parse().unwrap(),
+            reading: 512.0,
+            led_index: 1,
+            blink_ms: 1000,
+            pps: 4,
+            uptime_secs: 90,
+        }
+    }
+
+    #[test]
+    fn json_is_tagged_by_event_name() {
+        assert_eq!(
+            status().to_json(),
+            r#"{"event":"status","ts_ms":1500,"swarm_id":"pi2/abc","sender":"10.0.0.5","reading":512,"led_index":1,"blink_ms":1000,"pps":4,"uptime_secs":90}"#
+        );
+        let reset = Event::Reset {
+            ts_ms: 7,
+            kind: ResetKind::Hard,
+        };
+        assert_eq!(
+            reset.to_json(),
+            r#"{"event":"reset","ts_ms":7,"kind":"hard"}"#
+        );
+        let stuck = Event::SensorStuck {
+            ts_ms: 80,
+            swarm_id: "abc".to_string(),
+            reading: 512.75,
+            repeats: 51,
+        };
+        assert!(stuck.to_json().contains(r#""reading":512.75,"#));
+    }
+
+    #[test]
+    fn text_matches_the_console_lines() {
+        assert_eq!(
+            status().to_string(),
+            "[1500] STATUS master=abc@pi2 sender=10.0.0.5 value=512 blink=1000ms LED1 pps=4 uptime=90"
+        );
+        let timeout = Event::MasterTimeout {
+            ts_ms: 9000,
+            swarm_id: "abc".to_string(),
+            silent_ms: 5000,
+        };
+        assert_eq!(
+            timeout.to_string(),
+            "[9000] EVENT master_timeout  id=abc  silent=5000ms"
+        );
+        let roster = Event::Roster {
+            ts_ms: 9200,
+            swarms: vec![
+                RosterEntry {
+                    swarm_id: "abc".to_string(),
+                    last_seen_secs: 0,
+                    led_index: Some(0),
+                },
+                RosterEntry {
+                    swarm_id: "def".to_string(),
+                    last_seen_secs: 14,
+                    led_index: None,
+                },
+            ],
+        };
+        assert_eq!(
+            roster.to_string(),
+            "[9200] EVENT roster  abc=0s/LED0  def=14s"
+        );
+        let stuck = Event::SensorStuck {
+            ts_ms: 80,
+            swarm_id: "abc".to_string(),
+            reading: 512.0,
+            repeats: 51,
+        };
+        assert_eq!(
+            stuck.to_string(),
+            "[80] EVENT sensor_stuck  id=abc  reading=512  repeats=51"
+        );
+        let alarm = Event::ThresholdAlarm {
+            ts_ms: 100,
+            swarm_id: "abc".to_string(),
+            reading: 1010.0,
+            bound: AlarmBound::High,
+            threshold: 1000.0,
+        };
+        assert_eq!(
+            alarm.to_string(),
+            "[100] EVENT threshold_alarm  id=abc  bound=high  reading=1010  threshold=1000"
+        );
+        assert!(alarm.to_json().contains(r#""bound":"high""#));
+        let reload = Event::ConfigReload {
+            ts_ms: 110,
+            log_truncated: true,
+        };
+        assert_eq!(
+            reload.to_string(),
+            "[110] EVENT config_reload  log=truncated"
+        );
+        let selftest = Event::SelftestDone {
+            ts_ms: 120,
+            leds: 4,
+        };
+        assert_eq!(selftest.to_string(), "[120] EVENT selftest_done  leds=4");
+        let waiting = Event::WaitingForGpiochip {
+            ts_ms: 130,
+            attempt: 2,
+            attempts: 5,
+            retry_ms: 1000,
+        };
+        assert_eq!(
+            waiting.to_string(),
+            "[130] EVENT waiting_for_gpiochip  attempt=2/5  retry_in=1000ms"
+        );
+        assert!(waiting
+            .to_json()
+            .starts_with(r#"{"event":"waiting_for_gpiochip","#));
+        let fault = Event::GpioFault {
+            ts_ms: 9500,
+            failures: 3,
+            retry_ms: 2000,
+            error: "Failed to open /dev/gpiochip0".to_string(),
+        };
+        assert_eq!(
+            fault.to_string(),
+            "[9500] EVENT gpio_fault  failures=3  retry_in=2000ms  error=Failed to open /dev/gpiochip0"
+        );
+        let diagnostics = Event::Diagnostics {
+            ts_ms: 9700,
+            bad_utf8: 1,
+            bad_format: 4,
+            rejected_role: 0,
+        };
+        assert_eq!(
+            diagnostics.to_string(),
+            "[9700] EVENT diagnostics  bad_utf8=1  bad_format=4  rejected_role=0"
+        );
+        let heartbeat = Event::Heartbeat {
+            ts_ms: 60000,
+            uptime_secs: 60,
+            packets_received: 0,
+            master: None,
+            bad_utf8: 0,
+            bad_format: 2,
+            rejected_role: 0,
+            rejected_unknown_swarm: 1,
+            log_records_dropped: 0,
+        };
+        assert_eq!(
+            heartbeat.to_string(),
+            "[60000] EVENT heartbeat  uptime=60s  packets=0  master=none  bad_utf8=0  bad_format=2  rejected_role=0  rejected_unknown_swarm=1  log_dropped=0"
+        );
+        assert!(heartbeat.to_json().contains(r#""master":null,"#));
+        let reconnect = Event::GpioReconnect {
+            ts_ms: 9650,
+            errors: 5,
+            error: "Ioctl to set line values failed".to_string(),
+        };
+        assert_eq!(
+            reconnect.to_string(),
+            "[9650] EVENT gpio_reconnect  errors=5  error=Ioctl to set line values failed"
+        );
+        let closed = Event::GpioChannelClosed { ts_ms: 9600 };
+        assert_eq!(
+            closed.to_json(),
+            r#"{"event":"gpio_channel_closed","ts_ms":9600}"#
+        );
+    }
+}
@@ -0,0 +1,153 @@
+// Hands events to live subscribers (the web page's /events streams, gRPC clients), each through
+// its own bounded queue, so a slow or stuck subscriber never holds up the thread emitting them.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::{Mutex, PoisonError};
+
+// One subscriber's queue. `offer` must not block: false means the subscriber is gone for good,
+// a full queue just loses the item.
+pub trait Subscriber<T> {
+    fn offer(&self, item: T) -> bool;
+}
+
+impl<T> Subscriber<T> for SyncSender<T> {
+    fn offer(&self, item: T) -> bool {
+        !matches!(self.try_send(item), Err(TrySendError::Disconnected(_)))
+    }
+}
+
+pub struct Fanout<S> {
+    subscribers: Mutex<Vec<S>>,
+}
+
+impl<S> Fanout<S> {
+    pub const fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self, subscriber: S) {
+        self.lock().push(subscriber);
+    }
+
+    // Subscribers still registered; ones that left are only noticed by the next publish.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Offers the item to every subscriber and forgets the ones that are gone. `make` only runs
+    // when someone is subscribed.
+    pub fn publish<T: Clone>(&self, make: impl FnOnce() -> T)
+    where
+        S: Subscriber<T>,
+    {
+        let mut subscribers = self.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+        let item = make();
+        subscribers.retain(|subscriber| subscriber.offer(item.clone()));
+    }
+
+    // A panicking subscriber thread can't leave the list unusable for the others.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<S>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S> Default for Fanout<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A fixed number of concurrent streams; a slot is held until its guard is dropped.
+pub struct Slots {
+    used: AtomicUsize,
+    limit: usize,
+}
+
+impl Slots {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            used: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    // None when all slots are taken.
+    pub fn take(&self) -> Option<SlotGuard<'_>> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.limit).then_some(used + 1)
+            })
+            .ok()
+            .map(|_| SlotGuard { slots: self })
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+
+pub struct SlotGuard<'a> {
+    slots: &'a Slots,
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        self.slots.used.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn full_queues_drop_and_closed_ones_are_forgotten() {
+        let fanout = Fanout::new();
+        let (slow_tx, slow_rx) = mpsc::sync_channel(1);
+        let (gone_tx, gone_rx) = mpsc::sync_channel(1);
+        fanout.subscribe(slow_tx);
+        fanout.subscribe(gone_tx);
+        drop(gone_rx);
+
+        fanout.publish(|| 1);
+        assert_eq!(fanout.len(), 1);
+        // the slow subscriber's queue is full: 2 is lost for it, but it stays subscribed
+        fanout.publish(|| 2);
+        assert_eq!(fanout.len(), 1);
+        assert_eq!(slow_rx.try_recv(), Ok(1));
+        assert!(slow_rx.try_recv().is_err());
+        fanout.publish(|| 3);
+        assert_eq!(slow_rx.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn nothing_is_made_without_subscribers() {
+        let fanout: Fanout<mpsc::SyncSender<u32>> = Fanout::new();
+        fanout.publish(|| -> u32 { panic!("made for no one") });
+        assert!(fanout.is_empty());
+    }
+
+    #[test]
+    fn slots_are_returned_when_released() {
+        let slots = Slots::new(2);
+        let first = slots.take().unwrap();
+        let _second = slots.take().unwrap();
+        assert!(slots.take().is_none());
+        drop(first);
+        assert_eq!(slots.in_use(), 1);
+        assert!(slots.take().is_some());
+    }
+}
@@ -0,0 +1,414 @@
+// gRPC on network.grpc_port (cargo feature "grpc"): EventFeed.Subscribe streams the console
+// events as protobuf messages, from the same emit() as the text/JSON output and the web page.
+// tonic::Status is the error type of every handler, however large.
+#![allow(clippy::result_large_err)]
+
+use crate::web;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use raspberrypi::event::{AlarmBound, Event, ResetKind};
+use raspberrypi::fanout::{Fanout, Slots, Subscriber};
+use raspberrypi::state::SharedState;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("lightswarm");
+}
+
+use pb::event_feed_server::{EventFeed, EventFeedServer};
+
+// Events queued per subscriber before further ones are dropped for it, as for the web page.
+const SUBSCRIBER_QUEUE: usize = 64;
+// Subscribers at once; more are refused with RESOURCE_EXHAUSTED.
+const MAX_SUBSCRIBERS: usize = 8;
+
+static SUBSCRIBERS: Fanout<Queue> = Fanout::new();
+static SLOTS: Slots = Slots::new(MAX_SUBSCRIBERS);
+
+// A subscriber's queue, read by its response stream on the gRPC runtime.
+struct Queue(mpsc::Sender<Event>);
+
+impl Subscriber<Event> for Queue {
+    fn offer(&self, event: Event) -> bool {
+        !matches!(self.0.try_send(event), Err(TrySendError::Closed(_)))
+    }
+}
+
+// Hands an event to every subscriber without waiting (see Fanout::publish).
+pub fn publish(event: &Event) {
+    SUBSCRIBERS.publish(|| event.clone());
+}
+
+struct Feed {
+    state: Arc<Mutex<SharedState>>,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<pb::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl EventFeed for Feed {
+    type SubscribeStream = EventStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<pb::SubscribeRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let Some(slot) = SLOTS.take() else {
+            return Err(Status::resource_exhausted("too many event subscribers"));
+        };
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE);
+        if let Some(snapshot) = web::current_status(&self.state.lock().unwrap()) {
+            let _ = tx.try_send(snapshot);
+        }
+        SUBSCRIBERS.subscribe(Queue(tx));
+        let peer = request
+            .remote_addr()
+            .map_or_else(|| "?".to_string(), |addr| addr.to_string());
+        info!("gRPC: event stream to {peer}");
+        // the slot is given back when the client goes away and the stream is dropped
+        let events = ReceiverStream::new(rx).map(move |event| {
+            let _held = &slot;
+            Ok(pb::Event::from(&event))
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+// Serves until shutdown on a runtime of its own; the listener is bound by the caller so a
+// taken port is reported at startup.
+pub fn serve(listener: TcpListener, state: Arc<Mutex<SharedState>>, shutdown: Arc<AtomicBool>) {
+    if let Err(e) = run(listener, state, &shutdown) {
+        warn!("gRPC server error: {e:#}");
+    }
+}
+
+fn run(listener: TcpListener, state: Arc<Mutex<SharedState>>, shutdown: &AtomicBool) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the gRPC runtime")?;
+    runtime.block_on(async {
+        listener.set_nonblocking(true)?;
+        let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener)?);
+        let stopped = async {
+            while !shutdown.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tonic::transport::Server::builder()
+            .add_service(EventFeedServer::new(Feed { state }))
+            .serve_with_incoming_shutdown(incoming, stopped)
+            .await
+            .context("gRPC server failed")
+    })
+}
+
+impl From<&Event> for pb::Event {
+    fn from(event: &Event) -> Self {
+        use pb::event::Event as Kind;
+        let (ts_ms, kind) = match event.clone() {
+            Event::MasterSet {
+                ts_ms,
+                swarm_id,
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+            } => (
+                ts_ms,
+                Kind::MasterSet(pb::MasterSet {
+                    swarm_id,
+                    sender: sender.to_string(),
+                    reading,
+                    led_index: led_index as u32,
+                    blink_ms,
+                }),
+            ),
+            Event::MasterChange {
+                ts_ms,
+                from,
+                swarm_id,
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+                master_dwell_ms,
+            } => (
+                ts_ms,
+                Kind::MasterChange(pb::MasterChange {
+                    from,
+                    swarm_id,
+                    sender: sender.to_string(),
+                    reading,
+                    led_index: led_index as u32,
+                    blink_ms,
+                    master_dwell_ms,
+                }),
+            ),
+            Event::Status {
+                ts_ms,
+                swarm_id,
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+                pps,
+                uptime_secs,
+            } => (
+                ts_ms,
+                Kind::Status(pb::Status {
+                    swarm_id,
+                    sender: sender.to_string(),
+                    reading,
+                    led_index: led_index as u32,
+                    blink_ms,
+                    pps: pps as u64,
+                    uptime_secs,
+                }),
+            ),
+            Event::Reset { ts_ms, kind } => {
+                let kind = match kind {
+                    ResetKind::Soft => pb::ResetKind::Soft,
+                    ResetKind::Hard => pb::ResetKind::Hard,
+                };
+                let reset = pb::Reset { kind: kind.into() };
+                (ts_ms, Kind::Reset(reset))
+            }
+            Event::MasterTimeout {
+                ts_ms,
+                swarm_id,
+                silent_ms,
+            } => (
+                ts_ms,
+                Kind::MasterTimeout(pb::MasterTimeout {
+                    swarm_id,
+                    silent_ms,
+                }),
+            ),
+            Event::PacketLoss {
+                ts_ms,
+                swarm_id,
+                seq,
+                missing,
+                lost_total,
+            } => (
+                ts_ms,
+                Kind::PacketLoss(pb::PacketLoss {
+                    swarm_id,
+                    seq,
+                    missing,
+                    lost_total,
+                }),
+            ),
+            Event::SensorStuck {
+                ts_ms,
+                swarm_id,
+                reading,
+                repeats,
+            } => (
+                ts_ms,
+                Kind::SensorStuck(pb::SensorStuck {
+                    swarm_id,
+                    reading,
+                    repeats,
+                }),
+            ),
+            Event::Roster { ts_ms, swarms } => {
+                let swarms = swarms
+                    .into_iter()
+                    .map(|entry| pb::RosterEntry {
+                        swarm_id: entry.swarm_id,
+                        last_seen_secs: entry.last_seen_secs,
+                        led_index: entry.led_index.map(|idx| idx as u32),
+                    })
+                    .collect();
+                (ts_ms, Kind::Roster(pb::Roster { swarms }))
+            }
+            Event::ThresholdAlarm {
+                ts_ms,
+                swarm_id,
+                reading,
+                bound,
+                threshold,
+            } => {
+                let bound = match bound {
+                    AlarmBound::Low => pb::AlarmBound::Low,
+                    AlarmBound::High => pb::AlarmBound::High,
+                };
+                let alarm = pb::ThresholdAlarm {
+                    swarm_id,
+                    reading,
+                    bound: bound.into(),
+                    threshold,
+                };
+                (ts_ms, Kind::ThresholdAlarm(alarm))
+            }
+            Event::Diagnostics {
+                ts_ms,
+                bad_utf8,
+                bad_format,
+                rejected_role,
+            } => (
+                ts_ms,
+                Kind::Diagnostics(pb::Diagnostics {
+                    bad_utf8,
+                    bad_format,
+                    rejected_role,
+                }),
+            ),
+            Event::Heartbeat {
+                ts_ms,
+                uptime_secs,
+                packets_received,
+                master,
+                bad_utf8,
+                bad_format,
+                rejected_role,
+                rejected_unknown_swarm,
+                log_records_dropped,
+            } => (
+                ts_ms,
+                Kind::Heartbeat(pb::Heartbeat {
+                    uptime_secs,
+                    packets_received,
+                    master,
+                    bad_utf8,
+                    bad_format,
+                    rejected_role,
+                    rejected_unknown_swarm,
+                    log_records_dropped,
+                }),
+            ),
+            Event::ConfigReload {
+                ts_ms,
+                log_truncated,
+            } => (
+                ts_ms,
+                Kind::ConfigReload(pb::ConfigReload { log_truncated }),
+            ),
+            Event::SelftestDone { ts_ms, leds } => (
+                ts_ms,
+                Kind::SelftestDone(pb::SelftestDone { leds: leds as u32 }),
+            ),
+            Event::WaitingForGpiochip {
+                ts_ms,
+                attempt,
+                attempts,
+                retry_ms,
+            } => (
+                ts_ms,
+                Kind::WaitingForGpiochip(pb::WaitingForGpiochip {
+                    attempt,
+                    attempts,
+                    retry_ms,
+                }),
+            ),
+            Event::GpioFault {
+                ts_ms,
+                failures,
+                retry_ms,
+                error,
+            } => (
+                ts_ms,
+                Kind::GpioFault(pb::GpioFault {
+                    failures,
+                    retry_ms,
+                    error,
+                }),
+            ),
+            Event::GpioReconnect {
+                ts_ms,
+                errors,
+                error,
+            } => (
+                ts_ms,
+                Kind::GpioReconnect(pb::GpioReconnect { errors, error }),
+            ),
+            Event::GpioChannelClosed { ts_ms } => {
+                (ts_ms, Kind::GpioChannelClosed(pb::GpioChannelClosed {}))
+            }
+        };
+        pb::Event {
+            ts_ms: ts_ms as u64,
+            event: Some(kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raspberrypi::blink::BlinkMap;
+    use raspberrypi::config::{BlinkConfig, Config};
+
+    #[test]
+    fn events_map_to_their_messages() {
+        let event = Event::MasterSet {
+            ts_ms: 1234,
+            swarm_id: "abc".to_string(),
+            sender: "10.0.0.5".parse().unwrap(),
+            reading: 512.0,
+            led_index: 1,
+            blink_ms: 1000,
+        };
+        let message = pb::Event::from(&event);
+        assert_eq!(message.ts_ms, 1234);
+        let Some(pb::event::Event::MasterSet(master)) = message.event else {
+            panic!("not a master_set: {message:?}");
+        };
+        assert_eq!(master.swarm_id, "abc");
+        assert_eq!(master.sender, "10.0.0.5");
+        assert_eq!(master.led_index, 1);
+
+        let reset = pb::Event::from(&Event::Reset {
+            ts_ms: 5,
+            kind: ResetKind::Hard,
+        });
+        let Some(pb::event::Event::Reset(reset)) = reset.event else {
+            panic!("not a reset");
+        };
+        assert_eq!(reset.kind(), pb::ResetKind::Hard);
+    }
+
+    #[test]
+    fn streams_hold_a_slot_until_dropped() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let feed = Feed {
+            state: Arc::new(Mutex::new(SharedState::new(
+                Arc::new(Config::default()),
+                blink_map,
+            ))),
+        };
+        runtime.block_on(async {
+            let subscribe = || feed.subscribe(Request::new(pb::SubscribeRequest {}));
+            let mut streams = Vec::new();
+            for _ in 0..MAX_SUBSCRIBERS {
+                streams.push(subscribe().await.unwrap().into_inner());
+            }
+            let refused = subscribe().await.err().unwrap();
+            assert_eq!(refused.code(), tonic::Code::ResourceExhausted);
+
+            // no Master yet, so the first message is the next event
+            let event = Event::GpioChannelClosed { ts_ms: 3 };
+            publish(&event);
+            let message = streams[0].next().await.unwrap().unwrap();
+            assert_eq!(message, pb::Event::from(&event));
+
+            // a client going away drops its stream, which gives the slot back
+            streams.pop();
+            assert_eq!(SLOTS.in_use(), MAX_SUBSCRIBERS - 1);
+            assert!(subscribe().await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,37 @@
+// InfluxDB line protocol for readings sent to an InfluxDB UDP listener.
+
+// Tag values escape commas, spaces and equals signs with a backslash.
+fn escape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | ' ' | '=' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// reading,swarm=<id> value=<r>,blink_ms=<ms> <ts_ns> (an unsuffixed value is a float field,
+// so whole and fractional readings share one field type)
+pub fn reading_line(swarm_id: &str, reading: f64, blink_ms: u64, ts_ns: i64) -> String {
+    let swarm = escape_tag(swarm_id);
+    format!("reading,swarm={swarm} value={reading},blink_ms={blink_ms} {ts_ns}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_protocol() {
+        assert_eq!(
+            reading_line("abc", 512.0, 1058, 1_700_000_000_000_000_000),
+            "reading,swarm=abc value=512,blink_ms=1058 1700000000000000000"
+        );
+        assert_eq!(
+            reading_line("a b,c=d", 1.5, 2, 3),
+            r"reading,swarm=a\ b\,c\=d value=1.5,blink_ms=2 3"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+// Swarm id -> LED index assignments kept in a small JSON file (gpio.led_map_file).
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// The saved assignments; a missing file is an empty map.
+pub fn load(path: &Path) -> Result<HashMap<String, usize>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// Written to a temporary file first, so a crash never leaves a half-written map.
+pub fn save(path: &Path, map: &HashMap<String, usize>) -> Result<()> {
+    let sorted: BTreeMap<&String, &usize> = map.iter().collect();
+    let json = serde_json::to_string_pretty(&sorted).expect("LED maps always serialize");
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json + "\n").with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_map_loads_back() {
+        let dir = std::env::temp_dir().join(format!("lightswarm-ledmap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("leds.json");
+        assert!(load(&path).unwrap().is_empty());
+
+        let map = HashMap::from([("kitchen".to_string(), 1), ("hall".to_string(), 0)]);
+        save(&path, &map).unwrap();
+        assert_eq!(load(&path).unwrap(), map);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"hall\": 0,\n  \"kitchen\": 1\n}\n"
+        );
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,19 @@
+// Pure LightSwarm logic (no GPIO; sockets only behind source::PacketSource), shared by the
+// binary and its tests.
+pub mod auth;
+pub mod blink;
+pub mod button;
+pub mod calibrate;
+pub mod config;
+pub mod event;
+pub mod fanout;
+pub mod influx;
+pub mod ledmap;
+pub mod logfile;
+pub mod metrics;
+pub mod polarity;
+pub mod protocol;
+pub mod replay;
+pub mod source;
+pub mod state;
+pub mod stats;
@@ -0,0 +1,110 @@
+// log.per_swarm: one sensor_<id> log file per swarm, next to log.path.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PREFIX: &str = "sensor_";
+// Longer ids are cut, well below any filesystem's file name limit.
+const MAX_NAME_LEN: usize = 64;
+
+// The id as one file name component: anything but ASCII letters, digits, '-' and '_' (path
+// separators, dots, control characters) becomes '_', so no id can leave the log directory.
+// Ids differing only in those characters share a file; the records still carry the full id.
+pub fn sanitize(swarm_id: &str) -> String {
+    let name: String = swarm_id
+        .chars()
+        .take(MAX_NAME_LEN)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+// <log.path's directory>/sensor_<id><log.path's extension>
+pub fn swarm_path(base: &Path, swarm_id: &str) -> PathBuf {
+    let mut name = format!("{PREFIX}{}", sanitize(swarm_id));
+    if let Some(ext) = base.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    base.with_file_name(name)
+}
+
+// Every per-swarm file in log.path's directory, including those of earlier sessions;
+// rotated files (sensor_<id>.<n><ext>) are left out.
+pub fn swarm_paths(base: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to list {}", dir.display()))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(id) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(PREFIX))
+        else {
+            continue;
+        };
+        if sanitize(id) == id && path.file_name() == swarm_path(base, id).file_name() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_cannot_escape_the_directory() {
+        let base = Path::new("/var/log/lightswarm/sensor_readings.txt");
+        assert_eq!(
+            swarm_path(base, "kitchen-1"),
+            Path::new("/var/log/lightswarm/sensor_kitchen-1.txt")
+        );
+        assert_eq!(
+            swarm_path(base, "../../etc/passwd"),
+            Path::new("/var/log/lightswarm/sensor_______etc_passwd.txt")
+        );
+        assert_eq!(sanitize("a\\b\0c"), "a_b_c");
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize(&"x".repeat(100)).len(), MAX_NAME_LEN);
+        assert_eq!(swarm_path(Path::new("log"), "a"), Path::new("sensor_a"));
+    }
+
+    #[test]
+    fn lists_live_swarm_files_only() {
+        let dir = std::env::temp_dir().join(format!("lightswarm-logfile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "sensor_a.txt",
+            "sensor_b.txt",
+            "sensor_a.1.txt",
+            "sensor_c.csv",
+            "other.txt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let base = dir.join("readings.txt");
+        assert_eq!(
+            swarm_paths(&base).unwrap(),
+            [dir.join("sensor_a.txt"), dir.join("sensor_b.txt")]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,741 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use raspberrypi::config::{LogConfig, LogFormat, LogTimestamp};
+use raspberrypi::logfile;
+use raspberrypi::protocol::Role;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CSV_HEADER: &str = "timestamp,swarm_id,reading,sender_ip";
+// log.record_seq
+const CSV_SEQ_COLUMN: &str = ",seq";
+// log.blink_fields
+const CSV_BLINK_COLUMNS: &str = ",blink_ms,led_index";
+// Records waiting for the writer thread; more are dropped (and counted) rather than
+// stalling the receive loop behind a slow SD card.
+const LOG_QUEUE: usize = 1024;
+
+// One sensor log record; the optional fields are only written in the text format, except for
+// record_seq and the blink fields.
+pub struct LogRecord {
+    // receive time, which is what the record's timestamp shows
+    pub time: DateTime<Utc>,
+    pub record_seq: Option<u64>,
+    pub role: Role,
+    pub swarm_id: String,
+    pub reading: f64,
+    pub sender: SocketAddr,
+    pub raw: Option<String>,
+    pub out_of_range: bool,
+    // log.blink_fields: the interval mapped from the reading, and the swarm's LED if it has one
+    pub blink: Option<(u64, Option<usize>)>,
+}
+
+impl LogRecord {
+    // `next` would be logged as the same line, apart from its timestamp and record_seq.
+    fn repeated_by(&self, next: &LogRecord) -> bool {
+        self.role == next.role
+            && self.swarm_id == next.swarm_id
+            && self.reading == next.reading
+            && self.sender.ip() == next.sender.ip()
+            && self.raw == next.raw
+            && self.out_of_range == next.out_of_range
+            && self.blink == next.blink
+    }
+}
+
+enum LogCmd {
+    Record(LogRecord),
+    // Clear the log. Records sent before it are dropped with it, even if still buffered.
+    Truncate(mpsc::Sender<Result<()>>),
+    // Write out everything buffered, including records held by log.dedup (reset, shutdown).
+    Flush(mpsc::Sender<()>),
+}
+
+// Writes the sensor log. Appending only queues the record, without blocking; a background
+// thread does all file I/O, keeping the files open and writing their lines in batches (every
+// log.flush_ms, or once log.buffer_bytes are buffered), and syncing them every log.sync_ms.
+#[derive(Clone)]
+pub struct LogWriter {
+    tx: mpsc::SyncSender<LogCmd>,
+}
+
+impl LogWriter {
+    pub fn start(cfg: &LogConfig) -> Self {
+        let (tx, rx) = mpsc::sync_channel(LOG_QUEUE);
+        let mut files = LogFiles::new(cfg);
+        let flush_every = Duration::from_millis(cfg.flush_ms);
+        let sync_every = (cfg.sync_ms > 0).then(|| Duration::from_millis(cfg.sync_ms));
+        thread::spawn(move || {
+            // set while lines are buffered: when they are written at the latest
+            let mut flush_at: Option<Instant> = None;
+            // set while written lines are not synced: when they are synced at the latest
+            let mut sync_at: Option<Instant> = None;
+            loop {
+                // log.dedup: when the oldest held run is due, like a buffered line
+                let held_until = files.held_since().map(|since| since + flush_every);
+                let cmd = match flush_at.into_iter().chain(sync_at).chain(held_until).min() {
+                    Some(deadline) => {
+                        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match cmd {
+                    Ok(LogCmd::Record(record)) => files.append(record),
+                    Ok(LogCmd::Truncate(done)) => {
+                        let _ = done.send(files.truncate());
+                    }
+                    Ok(LogCmd::Flush(done)) => {
+                        files.release_held();
+                        files.flush();
+                        if sync_every.is_some() {
+                            files.sync();
+                        }
+                        let _ = done.send(());
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        files.release_held();
+                        files.flush();
+                        if sync_every.is_some() {
+                            files.sync();
+                        }
+                        return;
+                    }
+                }
+                let now = Instant::now();
+                // a steady reading still reaches the file, one line per flush_ms
+                let released = files.release_held_older(now, flush_every);
+                if !files.buffered() {
+                    flush_at = None;
+                } else if released || flush_at.is_some_and(|deadline| now >= deadline) {
+                    files.flush();
+                    flush_at = None;
+                } else if flush_at.is_none() {
+                    flush_at = Some(now + flush_every);
+                }
+                if let Some(sync_every) = sync_every {
+                    if !files.unsynced() {
+                        sync_at = None;
+                    } else if sync_at.is_some_and(|deadline| now >= deadline) {
+                        files.sync();
+                        sync_at = None;
+                    } else if sync_at.is_none() {
+                        sync_at = Some(now + sync_every);
+                    }
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    // Queues the record; false when it was dropped (the queue is full or the writer is gone).
+    pub fn append(&self, record: LogRecord) -> bool {
+        self.tx.try_send(LogCmd::Record(record)).is_ok()
+    }
+
+    // Clears the log (every per-swarm file with log.per_swarm), dropping what is still buffered.
+    pub fn truncate(&self) -> Result<()> {
+        let (done, result) = mpsc::channel();
+        let stopped = || anyhow!("Log writer stopped");
+        self.tx
+            .send(LogCmd::Truncate(done))
+            .map_err(|_| stopped())?;
+        result.recv().map_err(|_| stopped())?
+    }
+
+    // Returns once everything appended so far has been written.
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        if self.tx.send(LogCmd::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+// An open log file and the lines not written to it yet.
+struct LogFile {
+    file: File,
+    pending: Vec<u8>,
+    // the file's size including the pending lines, for size-based rotation
+    written: u64,
+    // lines were written since the last sync_data
+    unsynced: bool,
+    // log.dedup: the last record's run, not written yet
+    held: Option<Held>,
+}
+
+// A record and how many times it was received in a row (log.dedup).
+struct Held {
+    record: LogRecord,
+    count: u64,
+    // when the first of them was received
+    since: Instant,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {} for append", path.display()))?;
+        // the log is kept across restarts, so rotation starts from its current size
+        let written = file.metadata().map_or(0, |m| m.len());
+        Ok(Self {
+            file,
+            pending: Vec::new(),
+            written,
+            unsynced: false,
+            held: None,
+        })
+    }
+
+    // Pending lines are dropped when the write fails, so a full disk can't grow the buffer.
+    fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let result = self.file.write_all(&self.pending);
+        self.pending.clear();
+        self.unsynced = true;
+        result.context("Failed to write log lines")
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.unsynced {
+            self.unsynced = false;
+            self.file.sync_data().context("Failed to sync log file")?;
+        }
+        Ok(())
+    }
+}
+
+struct LogFiles {
+    cfg: LogConfig,
+    open: HashMap<PathBuf, LogFile>,
+    // a write error is reported once, not for every record until it clears
+    failing: bool,
+}
+
+impl LogFiles {
+    fn new(cfg: &LogConfig) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            open: HashMap::new(),
+            failing: false,
+        }
+    }
+
+    fn buffered(&self) -> bool {
+        self.open.values().any(|f| !f.pending.is_empty())
+    }
+
+    fn unsynced(&self) -> bool {
+        self.open.values().any(|f| f.unsynced)
+    }
+
+    fn append(&mut self, record: LogRecord) {
+        let path = match self.cfg.per_swarm {
+            true => logfile::swarm_path(&self.cfg.path, &record.swarm_id),
+            false => self.cfg.path.clone(),
+        };
+        let result = self.append_to(&path, record);
+        if result.is_err() {
+            // reopened by the next record
+            self.open.remove(&path);
+        }
+        self.report(result);
+    }
+
+    fn append_to(&mut self, path: &Path, record: LogRecord) -> Result<()> {
+        let cfg = &self.cfg;
+        let file = match self.open.entry(path.to_path_buf()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(LogFile::open(path)?),
+        };
+        if !cfg.dedup || cfg.format != LogFormat::Text {
+            return write_record(cfg, path, file, &record, 1);
+        }
+        if let Some(held) = &mut file.held {
+            if held.record.repeated_by(&record) {
+                held.count += 1;
+                return Ok(());
+            }
+        }
+        let run = Held {
+            record,
+            count: 1,
+            since: Instant::now(),
+        };
+        match file.held.replace(run) {
+            Some(held) => write_record(cfg, path, file, &held.record, held.count),
+            None => Ok(()),
+        }
+    }
+
+    // When the oldest run held back by log.dedup began.
+    fn held_since(&self) -> Option<Instant> {
+        self.open
+            .values()
+            .filter_map(|f| f.held.as_ref())
+            .map(|held| held.since)
+            .min()
+    }
+
+    // Writes the records held back by log.dedup.
+    fn release_held(&mut self) {
+        self.release_held_if(|_| true);
+    }
+
+    // Writes the held runs that began at least `age` before `now`; true if there were any.
+    fn release_held_older(&mut self, now: Instant, age: Duration) -> bool {
+        self.release_held_if(|since| now.saturating_duration_since(since) >= age)
+    }
+
+    fn release_held_if(&mut self, due: impl Fn(Instant) -> bool) -> bool {
+        let mut result = Ok(());
+        let mut released = false;
+        for (path, file) in &mut self.open {
+            if let Some(held) = file.held.take_if(|held| due(held.since)) {
+                result = result.and(write_record(
+                    &self.cfg,
+                    path,
+                    file,
+                    &held.record,
+                    held.count,
+                ));
+                released = true;
+            }
+        }
+        self.report(result);
+        released
+    }
+
+    fn flush(&mut self) {
+        let mut result = Ok(());
+        for file in self.open.values_mut() {
+            result = result.and(file.flush());
+        }
+        self.report(result);
+    }
+
+    fn sync(&mut self) {
+        let mut result = Ok(());
+        for file in self.open.values_mut() {
+            result = result.and(file.sync());
+        }
+        self.report(result);
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        // closing the files drops their pending lines
+        self.open.clear();
+        truncate_logs(&self.cfg)
+    }
+
+    fn report(&mut self, result: Result<()>) {
+        match result {
+            Ok(()) => self.failing = false,
+            Err(e) if !self.failing => {
+                warn!("{e:#}");
+                self.failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+// Buffers the record's line, rotating the file first when it is full. `count`: how many
+// identical records the line stands for (log.dedup).
+fn write_record(
+    cfg: &LogConfig,
+    path: &Path,
+    file: &mut LogFile,
+    record: &LogRecord,
+    count: u64,
+) -> Result<()> {
+    if cfg.max_bytes > 0 && file.written >= cfg.max_bytes {
+        file.flush()?;
+        if cfg.sync_ms > 0 {
+            file.sync()?;
+        }
+        rotate_log(path, cfg.rotate_keep)?;
+        // a record held by log.dedup goes to the fresh file
+        let held = file.held.take();
+        *file = LogFile::open(path)?;
+        file.held = held;
+    }
+    let line = format_record(cfg, file.written == 0, record, count);
+    file.pending.extend_from_slice(line.as_bytes());
+    file.written += line.len() as u64;
+    if file.pending.len() >= cfg.buffer_bytes {
+        file.flush()?;
+    }
+    Ok(())
+}
+
+// `fresh`: the record starts a new (new, rotated or truncated) file.
+fn format_record(cfg: &LogConfig, fresh: bool, record: &LogRecord, count: u64) -> String {
+    let LogRecord {
+        time,
+        record_seq,
+        ref role,
+        ref swarm_id,
+        reading,
+        sender,
+        ref raw,
+        out_of_range,
+        blink,
+    } = *record;
+    let timestamp = match cfg.timestamp {
+        LogTimestamp::Rfc3339 => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        LogTimestamp::EpochMs => time.timestamp_millis().to_string(),
+    };
+    let mut line = String::new();
+    match cfg.format {
+        LogFormat::Text => {
+            line.push_str(&timestamp);
+            if let Some(seq) = record_seq {
+                line.push_str(&format!(" #{seq}"));
+            }
+            let ip = sender.ip();
+            line.push_str(&format!(" Swarm ID {swarm_id}: {reading} sender={ip}"));
+            if *role != Role::Master {
+                line.push_str(&format!(" role={role}"));
+            }
+            if out_of_range {
+                line.push_str(" out_of_range");
+            }
+            if let Some((blink_ms, led_index)) = blink {
+                line.push_str(&format!(" blink_ms={blink_ms}"));
+                if let Some(idx) = led_index {
+                    line.push_str(&format!(" led={idx}"));
+                }
+            }
+            if let Some(raw) = raw {
+                line.push_str(&format!(" raw={raw}"));
+            }
+            if count > 1 {
+                line.push_str(&format!(" (x{count})"));
+            }
+        }
+        LogFormat::Csv => {
+            // a fresh file starts with the header
+            if fresh {
+                line.push_str(CSV_HEADER);
+                if cfg.record_seq {
+                    line.push_str(CSV_SEQ_COLUMN);
+                }
+                if cfg.blink_fields {
+                    line.push_str(CSV_BLINK_COLUMNS);
+                }
+                line.push('\n');
+            }
+            line.push_str(&format!("{timestamp},{swarm_id},{reading},{}", sender.ip()));
+            if let Some(seq) = record_seq {
+                line.push_str(&format!(",{seq}"));
+            }
+            if let Some((blink_ms, led_index)) = blink {
+                // no LED (not a Master yet): an empty column
+                let led = led_index.map_or_else(String::new, |idx| idx.to_string());
+                line.push_str(&format!(",{blink_ms},{led}"));
+            }
+        }
+    }
+    line.push('\n');
+    line
+}
+
+fn truncate_log(path: &Path) -> Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .with_context(|| format!("Failed to truncate {}", path.display()))?;
+    Ok(())
+}
+
+// The log file, or with log.per_swarm every swarm's file (also those of earlier sessions).
+fn truncate_logs(cfg: &LogConfig) -> Result<()> {
+    if !cfg.per_swarm {
+        return truncate_log(&cfg.path);
+    }
+    for path in logfile::swarm_paths(&cfg.path)? {
+        truncate_log(&path)?;
+    }
+    Ok(())
+}
+
+// sensor_readings.txt -> sensor_readings.<n>.txt
+fn rotated_log_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.{n}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{n}"),
+    };
+    path.with_file_name(name)
+}
+
+// Shift <stem>.1 -> <stem>.2 ... (dropping the oldest) and move the live file to <stem>.1.
+fn rotate_log(path: &Path, keep: usize) -> Result<()> {
+    if keep == 0 {
+        return truncate_log(path);
+    }
+    let _ = fs::remove_file(rotated_log_path(path, keep));
+    for n in (1..keep).rev() {
+        let from = rotated_log_path(path, n);
+        if from.exists() {
+            fs::rename(&from, rotated_log_path(path, n + 1))
+                .with_context(|| format!("Failed to rename {}", from.display()))?;
+        }
+    }
+    fs::rename(path, rotated_log_path(path, 1))
+        .with_context(|| format!("Failed to rotate {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE_512: &str = "2024-01-02T03:04:05.123Z Swarm ID abc: 512 sender=192.168.1.42\n";
+
+    // An empty directory of its own for each test's files.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "lightswarm-logwriter-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Nothing is written by the timer during a test.
+    fn config(dir: &Path) -> LogConfig {
+        LogConfig {
+            path: dir.join("readings.txt"),
+            flush_ms: 60_000,
+            sync_ms: 0,
+            ..LogConfig::default()
+        }
+    }
+
+    fn record(swarm_id: &str, reading: f64) -> LogRecord {
+        LogRecord {
+            time: DateTime::from_timestamp_millis(1_704_164_645_123).unwrap(),
+            record_seq: None,
+            role: Role::Master,
+            swarm_id: swarm_id.to_string(),
+            reading,
+            sender: "192.168.1.42:4210".parse().unwrap(),
+            raw: None,
+            out_of_range: false,
+            blink: None,
+        }
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn records_are_written_in_batches() {
+        let dir = temp_dir("batch");
+        let cfg = config(&dir);
+        let mut files = LogFiles::new(&cfg);
+        files.append(record("abc", 512.0));
+        assert!(files.buffered());
+        assert_eq!(read(&cfg.path), "");
+        files.flush();
+        assert!(!files.buffered());
+        assert_eq!(read(&cfg.path), LINE_512);
+
+        // log.buffer_bytes = 0: every record is written right away
+        let cfg = LogConfig {
+            buffer_bytes: 0,
+            ..cfg
+        };
+        let mut files = LogFiles::new(&cfg);
+        files.append(record("abc", 513.0));
+        assert!(!files.buffered());
+        assert_eq!(read(&cfg.path).lines().count(), 2);
+
+        // the writer thread: flush returns once the queued records are written
+        let writer = LogWriter::start(&cfg);
+        assert!(writer.append(record("abc", 514.0)));
+        writer.flush();
+        assert_eq!(read(&cfg.path).lines().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncate_drops_buffered_records() {
+        let dir = temp_dir("truncate");
+        let cfg = config(&dir);
+        fs::write(&cfg.path, "old\n").unwrap();
+        let writer = LogWriter::start(&cfg);
+        assert!(writer.append(record("abc", 512.0)));
+        assert!(writer.append(record("abc", 513.0)));
+        writer.truncate().unwrap();
+        writer.flush();
+        assert_eq!(read(&cfg.path), "");
+
+        assert!(writer.append(record("abc", 512.0)));
+        writer.flush();
+        assert_eq!(read(&cfg.path), LINE_512);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn full_files_are_rotated() {
+        let dir = temp_dir("rotate");
+        // every record fills the file
+        let cfg = LogConfig {
+            max_bytes: 1,
+            rotate_keep: 2,
+            ..config(&dir)
+        };
+        let mut files = LogFiles::new(&cfg);
+        for reading in 1..=4 {
+            files.append(record("abc", f64::from(reading)));
+        }
+        files.flush();
+        let reading = |path: &Path| read(path).split(": ").nth(1).map(|s| s[..1].to_string());
+        assert_eq!(reading(&cfg.path).as_deref(), Some("4"));
+        assert_eq!(reading(&dir.join("readings.1.txt")).as_deref(), Some("3"));
+        assert_eq!(reading(&dir.join("readings.2.txt")).as_deref(), Some("2"));
+        assert!(!dir.join("readings.3.txt").exists());
+
+        // rotate_keep = 0 clears the file in place
+        rotate_log(&cfg.path, 0).unwrap();
+        assert_eq!(read(&cfg.path), "");
+        assert_eq!(reading(&dir.join("readings.1.txt")).as_deref(), Some("3"));
+        assert_eq!(rotated_log_path(Path::new("log"), 1), Path::new("log.1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_files_start_with_the_header() {
+        let dir = temp_dir("csv");
+        let cfg = LogConfig {
+            path: dir.join("readings.csv"),
+            format: LogFormat::Csv,
+            max_bytes: 1,
+            rotate_keep: 1,
+            ..config(&dir)
+        };
+        let mut files = LogFiles::new(&cfg);
+        files.append(record("abc", 512.0));
+        files.append(record("abc", 513.0));
+        files.flush();
+        let row = |reading| {
+            format!("{CSV_HEADER}\n2024-01-02T03:04:05.123Z,abc,{reading},192.168.1.42\n")
+        };
+        assert_eq!(read(&dir.join("readings.1.csv")), row(512));
+        assert_eq!(read(&cfg.path), row(513));
+
+        // a file kept from an earlier run already has its header
+        let cfg = LogConfig {
+            max_bytes: 0,
+            ..cfg
+        };
+        let mut files = LogFiles::new(&cfg);
+        files.append(record("abc", 514.0));
+        files.flush();
+        assert_eq!(read(&cfg.path).matches(CSV_HEADER).count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_rows_carry_the_record_seq() {
+        let cfg = LogConfig {
+            format: LogFormat::Csv,
+            record_seq: true,
+            blink_fields: true,
+            ..LogConfig::default()
+        };
+        let record = LogRecord {
+            record_seq: Some(7),
+            blink: Some((250, None)),
+            ..record("abc", 512.0)
+        };
+        assert_eq!(
+            format_record(&cfg, true, &record, 1),
+            "timestamp,swarm_id,reading,sender_ip,seq,blink_ms,led_index\n\
+             2024-01-02T03:04:05.123Z,abc,512,192.168.1.42,7,250,\n"
+        );
+    }
+
+    #[test]
+    fn dedup_holds_repeats_until_released() {
+        let dir = temp_dir("dedup");
+        let cfg = LogConfig {
+            dedup: true,
+            ..config(&dir)
+        };
+        let mut files = LogFiles::new(&cfg);
+        for _ in 0..3 {
+            files.append(record("abc", 512.0));
+        }
+        // a different record ends the run; it is held in turn
+        files.append(record("abc", 513.0));
+        files.flush();
+        assert_eq!(read(&cfg.path), LINE_512.replace('\n', " (x3)\n"));
+        files.release_held();
+        files.flush();
+        assert!(read(&cfg.path).ends_with("Swarm ID abc: 513 sender=192.168.1.42\n"));
+
+        // a reset writes the held run, clearing the log drops it
+        let writer = LogWriter::start(&cfg);
+        writer.truncate().unwrap();
+        assert!(writer.append(record("abc", 512.0)));
+        assert!(writer.append(record("abc", 512.0)));
+        writer.flush();
+        assert_eq!(read(&cfg.path), LINE_512.replace('\n', " (x2)\n"));
+        assert!(writer.append(record("abc", 512.0)));
+        writer.truncate().unwrap();
+        writer.flush();
+        assert_eq!(read(&cfg.path), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn held_runs_are_written_after_flush_ms() {
+        let dir = temp_dir("dedup-timer");
+        let cfg = LogConfig {
+            dedup: true,
+            flush_ms: 20,
+            ..config(&dir)
+        };
+        let mut files = LogFiles::new(&cfg);
+        files.append(record("abc", 512.0));
+        files.append(record("abc", 512.0));
+        let since = files.held_since().unwrap();
+        let flush_every = Duration::from_millis(cfg.flush_ms);
+        assert!(!files.release_held_older(since + flush_every / 2, flush_every));
+        assert!(files.release_held_older(since + flush_every, flush_every));
+        assert_eq!(files.held_since(), None);
+        files.flush();
+        assert_eq!(read(&cfg.path), LINE_512.replace('\n', " (x2)\n"));
+
+        // the writer thread does it on its own, with nothing else arriving
+        let writer = LogWriter::start(&cfg);
+        assert!(writer.append(record("abc", 513.0)));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while read(&cfg.path).lines().count() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(read(&cfg.path).ends_with("Swarm ID abc: 513 sender=192.168.1.42\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
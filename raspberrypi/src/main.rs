@@ -1,122 +1,245 @@
-use anyhow::{Context, Result};
-use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+mod cli;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod logwriter;
+mod mqtt;
+mod web;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use clap::Parser;
+use cli::Cli;
+use env_logger::Env;
+use gpio_cdev::{
+    Chip, EventRequestFlags, EventType, LineDirection, LineEventHandle, LineHandle,
+    LineRequestFlags,
+};
+use log::{debug, error, info, warn};
+use logwriter::{LogRecord, LogWriter};
+use mqtt::Publisher;
+use raspberrypi::auth::{sign_frame, verify_frame};
+use raspberrypi::blink::{blink_interval, brightness_duty, reading_color, BlinkMap};
+use raspberrypi::button::{classify_press, debounced_press, PressKind};
+use raspberrypi::calibrate::{suggest_range, with_blink_range};
+use raspberrypi::config::{
+    self, Config, OutOfRange, OutputFormat, PwmMode, RawPayloadLog, RgbMode, ShutdownLeds,
+    WireProtocol,
+};
+use raspberrypi::event::{Event, ResetKind};
+use raspberrypi::fanout::Slots;
+use raspberrypi::influx;
+use raspberrypi::ledmap;
+use raspberrypi::metrics;
+use raspberrypi::polarity::{button_down, led_level};
+use raspberrypi::protocol::{
+    ack_frame, display_swarm_id, forward_inner, is_reset_request, is_status_query, parse_binary,
+    parse_message, status_reply, take_line_or_frame, Markers, Packet, ParseError, Role,
+    BINARY_FRAME_LEN, BINARY_ID_LEN, BINARY_MASTER, BINARY_SLAVE, FORWARD_ROLE, RESET_REQUESTED,
+};
+use raspberrypi::replay::{self, ReplayItem, ReplayLine};
+use raspberrypi::source::{PacketSource, ReceiveOnly};
+use raspberrypi::state::{jittered_deadline, SharedState};
+use raspberrypi::stats::{summary_lines, MovingAverage};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::ffi::CString;
+use std::fs;
+use std::io::{BufRead, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpListener, TcpStream, UdpSocket,
+};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     mpsc,
-    Arc, Mutex,
+    Arc, Mutex, PoisonError,
 };
 use std::thread;
 use std::time::{Duration, Instant};
 
-// ===== GPIO (BCM pins) =====
-const BUTTON_PIN: u32 = 26;
-const WHITE_LED_PIN: u32 = 18;
-const RGB_LED_PINS: [u32; 3] = [17, 22, 27];
+// ===== TCP ingest =====
+// Bytes buffered without a complete frame or line before the connection buffer is discarded.
+const TCP_MAX_PENDING: usize = 1024;
+// An ACK the client isn't reading gives up after this long instead of stalling the receive loop.
+const TCP_ACK_TIMEOUT: Duration = Duration::from_millis(100);
+// Frames waiting for the receive loop. A full queue stops reading the connections, so TCP flow
+// control slows their senders down instead of the frames piling up in memory.
+const TCP_QUEUE: usize = 256;
+// Connections served at once, each a thread; more are closed right after accept.
+const TCP_MAX_CONNS: usize = 16;
 
-// ===== UDP / Protocol =====
-const PORT: u16 = 4210;
-const RPI_START: &str = "+++";
-const RPI_END: &str = "***";
+static TCP_SLOTS: Slots = Slots::new(TCP_MAX_CONNS);
 
-// ===== Blink mapping (same mapping as your ESP) =====
-const X1: f64 = 24.0;
-const Y1: f64 = 2010.0 / 1000.0;
-const X2: f64 = 1024.0;
-const Y2: f64 = 10.0 / 1000.0;
+// A frame read from a TCP connection, queued for the receive loop. With network.tcp_ack the
+// connection comes along, for the ACK once the frame has been handled.
+struct TcpFrame {
+    bytes: Vec<u8>,
+    peer: SocketAddr,
+    reply: Option<Arc<TcpStream>>,
+}
 
-// ===== Terminal logging rate =====
-const STATUS_PRINT_MS: u64 = 1000;
+// Where an accepted frame is confirmed: the UDP source it came in on, or its TCP connection.
+enum AckTo<'a> {
+    Udp(&'a mut dyn PacketSource),
+    Tcp(&'a TcpStream),
+}
 
-// ===== State =====
-#[derive(Debug)]
-struct SharedState {
-    swarm_to_led: HashMap<String, usize>,
-    next_led_index: usize,
+// ===== Multicast reset =====
+// Reset packets stay on the local network segment.
+const MULTICAST_TTL: u32 = 1;
 
-    // Blink state for the currently blinking LED (only one should blink: the current Master)
-    led_state: bool,
-    previous_toggle: Instant,
+// ===== Forwarding =====
+// Relayed frames waiting to be sent; more are dropped rather than slowing the receive loop.
+const FORWARD_QUEUE: usize = 256;
 
-    // For terminal output
-    last_master_id: Option<String>,
-    last_reading: Option<i32>,
-    last_status_print: Instant,
+// ===== White LED (reset indication) =====
+const WHITE_LED_MS: u64 = 3000;
 
-    // Program start for timestamps
-    start: Instant,
-}
+// ===== Startup self-test =====
+// How long each LED stays on (and then off) while the self-test steps through them.
+const SELFTEST_STEP_MS: u64 = 200;
 
-impl SharedState {
-    fn new() -> Self {
-        Self {
-            swarm_to_led: HashMap::new(),
-            next_led_index: 0,
-            led_state: false,
-            previous_toggle: Instant::now(),
-            last_master_id: None,
-            last_reading: None,
-            last_status_print: Instant::now(),
-            start: Instant::now(),
-        }
-    }
+// ===== Software PWM =====
+// 100 Hz: fast enough not to flicker noticeably, slow enough for sleep-based timing.
+const PWM_PERIOD_MS: u64 = 10;
 
-    fn ts_ms(&self) -> u128 {
-        self.start.elapsed().as_millis()
-    }
+// ===== GPIO supervisor =====
+// Delay before restarting a failed GPIO thread, doubling per consecutive failure up to the max.
+const GPIO_RETRY_MS: u64 = 500;
+const GPIO_RETRY_MAX_MS: u64 = 30_000;
+// Consecutive failures before they are reported as gpio_fault events.
+const GPIO_FAULT_AFTER: u32 = 3;
+// Consecutive failed LED writes before the GPIO thread re-opens the chip and requests its
+// lines again (a brownout can reset the controller under the open handles); fewer are taken
+// as a passing glitch.
+const GPIO_RECONNECT_AFTER: u32 = 5;
+// Failed LED writes are warned about at most this often (with the count since the last warning).
+const GPIO_ERROR_WARN_MS: u64 = 10_000;
+// Longest wait between two attempts to open the gpiochip (gpio.chip_retry_ms doubles up to it).
+const CHIP_RETRY_MAX_MS: u64 = 5000;
+// Tried in order without gpio.chip: the Pi 5 header is on gpiochip4, older Pis use gpiochip0.
+const GPIO_CHIPS: [&str; 2] = ["/dev/gpiochip4", "/dev/gpiochip0"];
+// Label of the Pi 1-4 gpiochips (pinctrl-bcm2835, pinctrl-bcm2711), whose driver switches a
+// freed line to input.
+const BCM_CHIP_LABEL: &str = "pinctrl-bcm2";
 
-    fn led_label(idx: usize) -> &'static str {
-        match idx {
-            0 => "LED0",
-            1 => "LED1",
-            2 => "LED2",
-            _ => "LED?",
-        }
-    }
-
-    fn assign_led_index(&mut self, swarm_id: &str) -> usize {
-        if let Some(&idx) = self.swarm_to_led.get(swarm_id) {
-            return idx;
-        }
-        let idx = self.next_led_index;
-        self.swarm_to_led.insert(swarm_id.to_string(), idx);
-        self.next_led_index = (self.next_led_index + 1) % 3;
-        idx
-    }
-
-    fn reset(&mut self) {
-        self.swarm_to_led.clear();
-        self.next_led_index = 0;
-        self.led_state = false;
-        self.previous_toggle = Instant::now();
-        self.last_master_id = None;
-        self.last_reading = None;
-        self.last_status_print = Instant::now();
-    }
-}
+// ===== Blink timer =====
+// Longest the timer sleeps before re-reading the interval, so a new reading takes effect quickly.
+const BLINK_TICK_MS: u64 = 10;
 
 enum GpioCmd {
     AllRgbOff,
-    BlinkRgb { idx: usize, on: bool },
+    // duty: brightness in percent while on (100 = fully on, no PWM)
+    BlinkRgb { idx: usize, on: bool, duty: u8 },
+    // color mode: all three channels together, duty per channel in percent (red, green, blue)
+    BlinkColor { on: bool, duty: [u8; 3] },
     WhiteOnFor3s,
 }
 
-fn open_chip() -> Result<Chip> {
-    if let Ok(chip) = Chip::new("/dev/gpiochip4") {
-        return Ok(chip);
+// Everything the GPIO thread needs, shared across its restarts.
+struct GpioContext {
+    cfg: Arc<Config>,
+    state: Arc<Mutex<SharedState>>,
+    reset_flag: Arc<AtomicBool>,
+    // set by the UDP loop for an accepted remote reset, handled like a long press
+    remote_reset: Arc<AtomicBool>,
+    shutdown: Arc<AtomicBool>,
+    // sends RESET_REQUESTED to the reset address on a long press (none when replaying)
+    reset_sock: Option<(UdpSocket, SocketAddr)>,
+    mqtt: Option<Publisher>,
+    // cleared on a long press
+    log: LogWriter,
+    rx: Mutex<mpsc::Receiver<GpioCmd>>,
+}
+
+// gpio.chip, or else the first of GPIO_CHIPS that opens, retried gpio.chip_open_attempts times
+// with a doubling wait (waiting_for_gpiochip events) in case the chip is not there yet.
+fn open_chip(ctx: &GpioContext) -> Result<Chip> {
+    let cfg = &ctx.cfg;
+    let paths: Vec<&Path> = match &cfg.gpio.chip {
+        Some(path) => vec![path],
+        None => GPIO_CHIPS.iter().map(Path::new).collect(),
+    };
+    let attempts = cfg.gpio.chip_open_attempts.max(1);
+    let mut retry_ms = cfg.gpio.chip_retry_ms;
+    let mut attempt = 1;
+    loop {
+        let mut error = None;
+        for path in &paths {
+            match Chip::new(path) {
+                Ok(chip) => return Ok(chip),
+                Err(e) => error = Some(e),
+            }
+        }
+        if attempt >= attempts || ctx.shutdown.load(Ordering::SeqCst) {
+            let tried: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+            return Err(error.expect("at least one chip is tried"))
+                .with_context(|| format!("Failed to open {}", tried.join(" or ")));
+        }
+        let ts_ms = ctx.state.lock().unwrap().ts_ms();
+        let event = Event::WaitingForGpiochip {
+            ts_ms,
+            attempt,
+            attempts,
+            retry_ms,
+        };
+        emit(cfg.status.output, &event);
+        let until = Instant::now() + Duration::from_millis(retry_ms);
+        while Instant::now() < until && !ctx.shutdown.load(Ordering::SeqCst) {
+            thread::sleep((until - Instant::now()).min(Duration::from_millis(100)));
+        }
+        attempt += 1;
+        retry_ms = (retry_ms * 2).min(CHIP_RETRY_MAX_MS);
+    }
+}
+
+// --list-gpiochips: every gpiochip with its lines and their users, to find the chip (gpio.chip)
+// and line numbers (the gpio pins) on an unfamiliar board.
+fn list_gpiochips() -> Result<()> {
+    let mut chips = gpio_cdev::chips()
+        .context("Failed to list /dev/gpiochip*")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to open a gpiochip")?;
+    if chips.is_empty() {
+        println!("No /dev/gpiochip* devices found");
+    }
+    chips.sort_by(|a, b| a.path().cmp(b.path()));
+    for chip in &chips {
+        let path = chip.path().display();
+        println!("{path}  label={}  lines={}", chip.label(), chip.num_lines());
+        for line in chip.lines() {
+            let info = line
+                .info()
+                .with_context(|| format!("Failed to read line {} of {path}", line.offset()))?;
+            let name = info.name().unwrap_or("-");
+            let direction = match info.direction() {
+                LineDirection::In => "input",
+                LineDirection::Out => "output",
+            };
+            match info.consumer() {
+                Some(consumer) => println!(
+                    "  {:>3}  {name:<16} {direction:<6}  used by {consumer}",
+                    line.offset()
+                ),
+                None => println!("  {:>3}  {name:<16} {direction}", line.offset()),
+            }
+        }
     }
-    Chip::new("/dev/gpiochip0").context("Failed to open /dev/gpiochip4 or /dev/gpiochip0")
+    Ok(())
 }
 
-fn request_input(chip: &mut Chip, pin: u32, name: &str) -> Result<LineHandle> {
+fn request_button(chip: &mut Chip, pin: u32) -> Result<LineEventHandle> {
     let line = chip
         .get_line(pin)
         .with_context(|| format!("get_line failed for pin {pin}"))?;
-    line.request(LineRequestFlags::INPUT, 0, name)
-        .with_context(|| format!("request INPUT failed for pin {pin}"))
+    line.events(
+        LineRequestFlags::INPUT,
+        EventRequestFlags::BOTH_EDGES,
+        "button",
+    )
+    .with_context(|| format!("request events failed for pin {pin}"))
 }
 
 fn request_output(chip: &mut Chip, pin: u32, name: &str, initial: u8) -> Result<LineHandle> {
@@ -127,273 +250,2099 @@ fn request_output(chip: &mut Chip, pin: u32, name: &str, initial: u8) -> Result<
         .with_context(|| format!("request OUTPUT failed for pin {pin}"))
 }
 
-fn set_led(line: &LineHandle, on: bool) {
-    let _ = line.set_value(if on { 1 } else { 0 });
+// GPIO lines, or (with --simulate) stand-ins so the rest runs without hardware
+enum Led {
+    // an active-low LED lights when the line is driven to 0
+    Line { line: LineHandle, active_low: bool },
+    // prints "set_led <label> on=<bool>" instead of writing a pin
+    Sim(String),
+}
+
+enum Button {
+    // edge events in both directions; an active-low button is pressed at the falling edge
+    Line {
+        events: LineEventHandle,
+        active_low: bool,
+    },
+    // never pressed
+    Sim,
+}
+
+struct GpioLines {
+    button: Button,
+    white_led: Led,
+    rgb_leds: Vec<Led>,
+}
+
+// Failed LED writes: consecutive ones (any successful write starts the count over), and those
+// not yet added to the shared counter or warned about.
+#[derive(Default)]
+struct GpioErrors {
+    consecutive: u32,
+    last: String,
+    uncounted: u64,
+    unwarned: u64,
+    next_warn: Option<Instant>,
+}
+
+impl GpioErrors {
+    fn check(&mut self, result: Result<(), gpio_cdev::Error>) {
+        match result {
+            Ok(()) => self.consecutive = 0,
+            Err(e) => {
+                self.consecutive += 1;
+                self.uncounted += 1;
+                self.unwarned += 1;
+                self.last = e.to_string();
+            }
+        }
+    }
+
+    // Adds the new failures to the gpio_write_errors counter, and warns about them unless the
+    // last warning was less than GPIO_ERROR_WARN_MS ago (they are then part of the next one).
+    fn report(&mut self, state: &Mutex<SharedState>) {
+        if self.uncounted == 0 {
+            return;
+        }
+        let mut st = state.lock().unwrap();
+        st.gpio_write_errors += std::mem::take(&mut self.uncounted);
+        let now = Instant::now();
+        if self.next_warn.is_some_and(|t| now < t) {
+            return;
+        }
+        self.next_warn = Some(now + Duration::from_millis(GPIO_ERROR_WARN_MS));
+        warn!(
+            "[{}] WARN gpio_write_failed  failed={}  total={}  error={}",
+            st.ts_ms(),
+            std::mem::take(&mut self.unwarned),
+            st.gpio_write_errors,
+            self.last
+        );
+    }
+
+    // The count and latest error once GPIO_RECONNECT_AFTER writes in a row have failed.
+    fn take_reconnect(&mut self) -> Option<(u32, String)> {
+        if self.consecutive < GPIO_RECONNECT_AFTER {
+            return None;
+        }
+        Some((std::mem::take(&mut self.consecutive), self.last.clone()))
+    }
+}
+
+fn open_gpio(ctx: &GpioContext) -> Result<GpioLines> {
+    let cfg = &ctx.cfg.gpio;
+    if cfg.simulate {
+        return Ok(GpioLines {
+            button: Button::Sim,
+            white_led: Led::Sim("white".to_string()),
+            rgb_leds: (0..cfg.rgb_led_pins.len())
+                .map(|i| Led::Sim(format!("idx={i}")))
+                .collect(),
+        });
+    }
+    let mut chip = open_chip(ctx)?;
+    if cfg.leds_on_shutdown == ShutdownLeds::Hold && chip.label().starts_with(BCM_CHIP_LABEL) {
+        warn!(
+            "gpio.leds_on_shutdown = hold is not honoured on {}: the lines are freed at exit, \
+             which switches the pins to input, so the LEDs go dark anyway",
+            chip.label()
+        );
+    }
+    let button = request_button(&mut chip, cfg.button_pin)?;
+    // every LED starts off, whatever its polarity
+    let off = led_level(false, cfg.led_active_low);
+    let led = |line| Led::Line {
+        line,
+        active_low: cfg.led_active_low,
+    };
+    let white = request_output(&mut chip, cfg.white_led_pin, "white_led", off)?;
+    let white_led = led(white);
+    let mut rgb_leds = Vec::new();
+    for (i, pin) in cfg.rgb_led_pins.iter().enumerate() {
+        let h = request_output(&mut chip, *pin, &format!("rgb_led_{i}"), off)?;
+        rgb_leds.push(led(h));
+    }
+    Ok(GpioLines {
+        button: Button::Line {
+            events: button,
+            active_low: cfg.button_active_low,
+        },
+        white_led,
+        rgb_leds,
+    })
+}
+
+fn set_led(led: &Led, on: bool) -> Result<(), gpio_cdev::Error> {
+    match led {
+        Led::Line { line, active_low } => line.set_value(led_level(on, *active_low)),
+        Led::Sim(label) => {
+            info!("set_led {label} on={on}");
+            Ok(())
+        }
+    }
+}
+
+// Current level: true while pressed.
+fn button_pressed(button: &Button) -> bool {
+    match button {
+        Button::Line { events, active_low } => events
+            .get_value()
+            .is_ok_and(|v| button_down(v, *active_low)),
+        Button::Sim => false,
+    }
+}
+
+// Wait up to `timeout` for button edges; returns the level after each queued edge in order.
+fn wait_for_button(button: &mut Button, timeout: Duration) -> Vec<bool> {
+    let Button::Line { events, active_low } = button else {
+        thread::sleep(timeout);
+        return Vec::new();
+    };
+    let mut pfd = libc::pollfd {
+        fd: events.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let mut wait_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    let mut levels = Vec::new();
+    // SAFETY: `pfd` is a single valid pollfd that outlives the call
+    while unsafe { libc::poll(&mut pfd, 1, wait_ms) } > 0 {
+        let Ok(event) = events.get_event() else {
+            break;
+        };
+        let level = match event.event_type() {
+            EventType::FallingEdge => 0,
+            EventType::RisingEdge => 1,
+        };
+        levels.push(button_down(level, *active_low));
+        wait_ms = 0;
+    }
+    levels
+}
+
+// Software PWM for LEDs that are commanded on, for `span`: the channels of one RGB LED in
+// color mode, or the blinking Masters' LEDs (duty 0 = left alone). Each LED is switched off,
+// then on late in every period, so the lit ones are left on (matching their cached state)
+// when the span ends.
+fn pwm_pulse(leds: &[Led], duty: &[u8], span: Duration) -> Result<(), gpio_cdev::Error> {
+    if leds.iter().any(|led| matches!(led, Led::Sim(_))) {
+        // don't print every PWM edge
+        thread::sleep(span);
+        return Ok(());
+    }
+    let period = Duration::from_millis(PWM_PERIOD_MS);
+    // (switch-on offset within the period, LED) for the lit channels, earliest first
+    let mut lit: Vec<(Duration, &Led)> = leds
+        .iter()
+        .zip(duty.iter().copied())
+        .filter(|(_, duty)| *duty > 0)
+        .map(|(led, duty)| (period - period * u32::from(duty.min(100)) / 100, led))
+        .collect();
+    lit.sort_by_key(|(offset, _)| *offset);
+    let end = Instant::now() + span;
+    while Instant::now() < end {
+        let start = Instant::now();
+        for (offset, led) in &lit {
+            if !offset.is_zero() {
+                set_led(led, false)?;
+            }
+        }
+        for (offset, led) in &lit {
+            thread::sleep((start + *offset).saturating_duration_since(Instant::now()));
+            set_led(led, true)?;
+        }
+        thread::sleep((start + period).saturating_duration_since(Instant::now()));
+    }
+    Ok(())
 }
 
-fn truncate_log() -> Result<()> {
-    OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("sensor_readings.txt")
-        .context("Failed to truncate sensor_readings.txt")?;
+// Startup self-test: each RGB LED on then off in turn, then a pulse of the white LED.
+fn selftest(rgb_leds: &[Led], white_led: &Led) -> Result<(), gpio_cdev::Error> {
+    let step = Duration::from_millis(SELFTEST_STEP_MS);
+    for led in rgb_leds.iter().chain([white_led]) {
+        set_led(led, true)?;
+        thread::sleep(step);
+        set_led(led, false)?;
+        thread::sleep(step);
+    }
     Ok(())
 }
 
-fn append_log(swarm_id: &str, reading: i32) -> Result<()> {
-    let mut f = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("sensor_readings.txt")
-        .context("Failed to open sensor_readings.txt for append")?;
-    writeln!(f, "Swarm ID {}: {}", swarm_id, reading).context("Failed to write log line")?;
+// Skip the write when the pin was already commanded to this state; a failed write is
+// tried again by the next command.
+fn set_led_cached(line: &Led, last: &mut bool, on: bool) -> Result<(), gpio_cdev::Error> {
+    if *last != on {
+        set_led(line, on)?;
+        *last = on;
+    }
     Ok(())
 }
 
-// Accepts payloads:
-// 1) +++Master,<swarm_id>,<reading>***
-// 2) +++<swarm_id>,<reading>***    (optional fallback)
-fn parse_message(payload: &str) -> Option<(String, i32)> {
-    if !payload.starts_with(RPI_START) || !payload.ends_with(RPI_END) {
+fn format_raw_payload(mode: RawPayloadLog, bytes: &[u8]) -> Option<String> {
+    match mode {
+        RawPayloadLog::Off => None,
+        RawPayloadLog::Escaped => Some(format!("{:?}", String::from_utf8_lossy(bytes))),
+        RawPayloadLog::Hex => {
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            Some(format!("hex:{hex}"))
+        }
+    }
+}
+
+// +++RESET_REQUESTED*** with network.remote_reset on (and a valid tag when network.secret is set);
+// the sender still has to be checked against network.reset_allowed_ips.
+fn is_remote_reset(bytes: &[u8], cfg: &config::NetworkConfig) -> bool {
+    cfg.remote_reset
+        && std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|payload| authenticate(payload, cfg))
+            .is_some_and(|payload| is_reset_request(payload.as_bytes(), cfg.markers()))
+}
+
+// With network.secret set, only frames with a valid tag pass (returned without the tag).
+fn authenticate<'a>(payload: &'a str, cfg: &config::NetworkConfig) -> Option<Cow<'a, str>> {
+    match &cfg.secret {
+        Some(secret) => verify_frame(payload, secret.as_bytes(), cfg.markers()).map(Cow::Owned),
+        None => Some(Cow::Borrowed(payload)),
+    }
+}
+
+fn serve_tcp_conn(
+    mut stream: TcpStream,
+    frames: mpsc::SyncSender<TcpFrame>,
+    cfg: &config::NetworkConfig,
+) -> Result<()> {
+    let peer = stream.peer_addr().context("TCP peer address unavailable")?;
+    // ACKs are written by the receive loop after the frame went through the pipeline
+    let reply = match cfg.tcp_ack {
+        true => {
+            let writer = stream.try_clone().context("TCP stream clone failed")?;
+            writer
+                .set_write_timeout(Some(TCP_ACK_TIMEOUT))
+                .context("TCP write timeout")?;
+            Some(Arc::new(writer))
+        }
+        false => None,
+    };
+    let mut pending = Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut chunk).context("TCP read failed")?;
+        if n == 0 {
+            return Ok(());
+        }
+        pending.extend_from_slice(&chunk[..n]);
+
+        while let Some(bytes) = take_line_or_frame(&mut pending, cfg.markers()) {
+            let frame = TcpFrame {
+                bytes,
+                peer,
+                reply: reply.clone(),
+            };
+            // blocks while the queue is full
+            if frames.send(frame).is_err() {
+                return Ok(());
+            }
+        }
+        if pending.len() > TCP_MAX_PENDING {
+            pending.clear();
+        }
+    }
+}
+
+fn serve_metrics(server: &tiny_http::Server, state: &Mutex<SharedState>, shutdown: &AtomicBool) {
+    while !shutdown.load(Ordering::SeqCst) {
+        let request = match server.recv_timeout(Duration::from_millis(100)) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Metrics server error: {e}");
+                return;
+            }
+        };
+        let response = if request.url() == "/metrics" {
+            let body = metrics::render(&state.lock().unwrap());
+            let content_type = tiny_http::Header::from_bytes(
+                "Content-Type",
+                "text/plain; version=0.0.4; charset=utf-8",
+            )
+            .expect("static header");
+            tiny_http::Response::from_string(body).with_header(content_type)
+        } else {
+            tiny_http::Response::from_string("not found").with_status_code(404)
+        };
+        if let Err(e) = request.respond(response) {
+            debug!("Metrics response failed: {e}");
+        }
+    }
+}
+
+// Control console commands (one per line on stdin):
+//   set-map <x1> <y1> <x2> <y2>         (y values in seconds, like X1/Y1/X2/Y2)
+//   set-blink-bounds <min_ms> <max_ms>
+//   show-map
+fn apply_control_command(st: &mut SharedState, line: &str) -> Result<()> {
+    let mut words = line.split_whitespace();
+    let Some(cmd) = words.next() else {
+        return Ok(());
+    };
+    let args: Vec<f64> = words
+        .map(|w| {
+            w.parse::<f64>()
+                .with_context(|| format!("invalid number: {w}"))
+        })
+        .collect::<Result<_>>()?;
+
+    match (cmd, args.as_slice()) {
+        ("set-map", &[x1, y1, x2, y2]) => st.blink_map.set_map(x1, y1, x2, y2)?,
+        ("set-blink-bounds", &[min_ms, max_ms]) => st.blink_map.set_bounds(min_ms, max_ms)?,
+        ("show-map", &[]) => {}
+        ("set-map", _) => bail!("usage: set-map <x1> <y1> <x2> <y2>"),
+        ("set-blink-bounds", _) => bail!("usage: set-blink-bounds <min_ms> <max_ms>"),
+        _ => bail!("unknown command: {cmd}"),
+    }
+
+    let m = st.blink_map;
+    info!(
+        "[{}] EVENT blink_map  mapping={:?} x1={} y1={} x2={} y2={}  min={}ms max={}ms",
+        st.ts_ms(),
+        m.curve,
+        m.x1,
+        m.y1,
+        m.x2,
+        m.y2,
+        m.min_seconds * 1000.0,
+        m.max_seconds * 1000.0
+    );
+    Ok(())
+}
+
+// Checks a frame must pass before it is logged: authentication, parsing, allowlist, inventory
+// and reading range. Returns the packet and, per reading, whether it is out of range (but
+// flagged, not skipped); skipped samples of a batch are removed from the packet.
+fn accept_payload(
+    bytes: &[u8],
+    sender: SocketAddr,
+    cfg: &Config,
+    state: &Mutex<SharedState>,
+) -> Option<(Packet, Vec<bool>)> {
+    let net = &cfg.network;
+    let parsed = match net.protocol {
+        // the Pi's own reset broadcast is a text frame in either mode
+        WireProtocol::Binary if is_reset_request(bytes, net.markers()) => Err(ParseError::Reset),
+        WireProtocol::Binary => parse_binary(bytes),
+        WireProtocol::Text => {
+            let Ok(payload) = std::str::from_utf8(bytes) else {
+                state.lock().unwrap().decode_drops.bad_utf8 += 1;
+                return None;
+            };
+            let Some(payload) = authenticate(payload, net) else {
+                debug!("unauthenticated frame from {} dropped", sender.ip());
+                return None;
+            };
+            parse_message(&payload, net.aggregator_mode, net.markers())
+        }
+    };
+    let mut packet = match parsed {
+        Ok(packet) => packet,
+        Err(e) => {
+            let drops = &mut state.lock().unwrap().decode_drops;
+            match e {
+                ParseError::Format => drops.bad_format += 1,
+                ParseError::Role => drops.rejected_role += 1,
+                // the echo of a reset broadcast is expected, not a broken sender
+                ParseError::Reset => {}
+            }
+            return None;
+        }
+    };
+    let swarm_id = &packet.swarm_id;
+
+    // Id rules (network.swarm_id_max_len / swarm_id_charset), counted as rejected packets
+    if !cfg.network.swarm_id_allowed(swarm_id) {
+        debug!("swarm id {swarm_id:?} from {} rejected", sender.ip());
         return None;
     }
-    let inner = &payload[RPI_START.len()..payload.len() - RPI_END.len()];
 
-    // ignore reset packets
-    if inner == "RESET_REQUESTED" {
+    // Allowlist: ids outside it never get further than this
+    let allowed = &cfg.network.allowed_swarm_ids;
+    if !allowed.is_empty() && !allowed.contains(swarm_id) {
+        state.lock().unwrap().rejected_unknown_swarm += 1;
+        debug!("swarm {swarm_id} from {} not allowed, dropped", sender.ip());
         return None;
     }
 
-    let parts: Vec<&str> = inner.split(',').map(|s| s.trim()).collect();
-    match parts.as_slice() {
-        [swarm_id, reading] => {
-            let reading: i32 = reading.parse().ok()?;
-            Some((swarm_id.to_string(), reading))
+    // Inventory: flag ids outside the expected list (and optionally drop them)
+    let (ts_ms, unknown) = {
+        let mut st = state.lock().unwrap();
+        (st.ts_ms(), st.note_swarm_reported(swarm_id))
+    };
+    if let Some(first_sighting) = unknown {
+        if first_sighting {
+            let action = if cfg.inventory.drop_unknown {
+                "drop"
+            } else {
+                "accept"
+            };
+            warn!("[{ts_ms}] WARN unknown_swarm  id={swarm_id}  action={action}");
         }
-        [role, swarm_id, reading] => {
-            if *role != "Master" {
-                return None;
+        if cfg.inventory.drop_unknown {
+            return None;
+        }
+    }
+
+    // Range check: garbage from a misbehaving ADC is dropped, or logged flagged (each sample
+    // of a batch on its own)
+    let range = &cfg.reading;
+    let skip = range.out_of_range == OutOfRange::Skip;
+    let action = if skip { "skip" } else { "flag" };
+    let id = display_swarm_id(swarm_id);
+    let mut out_of_range = Vec::with_capacity(packet.readings.len());
+    packet.readings.retain(|&reading| {
+        let outside = !(range.min..=range.max).contains(&reading);
+        if outside {
+            warn!(
+                "[{ts_ms}] WARN reading_out_of_range  id={id}  value={reading}  range={}..={}  action={action}",
+                range.min,
+                range.max
+            );
+        }
+        let keep = !(outside && skip);
+        if keep {
+            out_of_range.push(outside);
+        }
+        keep
+    });
+    if packet.readings.is_empty() {
+        return None;
+    }
+
+    Some((packet, out_of_range))
+}
+
+// InfluxDB UDP listener (--influx-udp). Sends are non-blocking: a reading that cannot be sent
+// right away is dropped rather than delaying the receive loop.
+struct InfluxSink {
+    sock: UdpSocket,
+    addr: SocketAddr,
+}
+
+impl InfluxSink {
+    fn open(addr: SocketAddr) -> Result<Self> {
+        let local: SocketAddr = if addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let sock = UdpSocket::bind(local).context("Failed to bind InfluxDB UDP socket")?;
+        sock.set_nonblocking(true)
+            .context("Failed to set InfluxDB UDP socket non-blocking")?;
+        Ok(Self { sock, addr })
+    }
+
+    fn send(&self, swarm_id: &str, reading: f64, blink_ms: u64) {
+        let ts_ns = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let line = influx::reading_line(swarm_id, reading, blink_ms, ts_ns);
+        if let Err(e) = self.sock.send_to(line.as_bytes(), self.addr) {
+            debug!("InfluxDB: line dropped: {e}");
+        }
+    }
+}
+
+// Relays readings to network.forward_to from its own thread, so a slow send never holds up
+// the receive loop (best-effort: frames beyond FORWARD_QUEUE are dropped).
+struct Forwarder {
+    tx: mpsc::SyncSender<String>,
+    target: SocketAddr,
+}
+
+impl Forwarder {
+    fn start(sock: UdpSocket, target: SocketAddr) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<String>(FORWARD_QUEUE);
+        thread::spawn(move || {
+            for frame in rx {
+                if let Err(e) = sock.send_to(frame.as_bytes(), target) {
+                    debug!("Forward to {target} failed: {e}");
+                }
             }
-            let reading: i32 = reading.parse().ok()?;
-            Some((swarm_id.to_string(), reading))
+        });
+        Self { tx, target }
+    }
+
+    fn send(&self, frame: String) {
+        if self.tx.try_send(frame).is_err() {
+            debug!("Forward queue full, frame to {} dropped", self.target);
         }
-        _ => None,
     }
 }
 
-fn blink_interval_seconds(reading: i32) -> f64 {
-    let slope = (Y2 - Y1) / (X2 - X1);
-    let intercept = Y1 - slope * X1;
+// Optional outputs each logged reading is also published to.
+#[derive(Default)]
+struct Sinks {
+    mqtt: Option<Publisher>,
+    influx: Option<InfluxSink>,
+    forward: Option<Forwarder>,
+}
 
-    let x = (reading as f64).clamp(0.0, X2);
-    let mut seconds = slope * x + intercept;
-    if seconds < 0.005 {
-        seconds = 0.005;
+// +++<inner>*** as sent by the Pi, tagged when network.secret is set.
+fn outgoing_frame(inner: &str, cfg: &config::NetworkConfig) -> String {
+    match &cfg.secret {
+        Some(secret) => sign_frame(inner, secret.as_bytes(), cfg.markers()),
+        None => cfg.markers().frame(inner),
     }
-    seconds
 }
 
-fn main() -> Result<()> {
-    // ===== UDP init =====
-    let sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT))
-        .with_context(|| format!("Failed to bind UDP port {PORT}"))?;
-    sock.set_broadcast(true).context("Failed to enable broadcast")?;
-    sock.set_read_timeout(Some(Duration::from_millis(100)))
-        .context("Failed to set read timeout")?;
+// Full pipeline for one received frame: parse, log, update state (the blink timer drives the LEDs).
+// Every reading of a batch is logged and published; reading.batch picks the one that drives the
+// blinking. With `ack`, a frame whose readings were all queued for the log is confirmed to its
+// sender (UDP --ack, network.tcp_ack), echoing the last reading.
+fn handle_payload(
+    bytes: &[u8],
+    sender: SocketAddr,
+    ack: Option<AckTo>,
+    sinks: &Sinks,
+    log: &LogWriter,
+    cfg: &Config,
+    state: &Mutex<SharedState>,
+) {
+    state.lock().unwrap().note_received(Instant::now());
+    let Some((packet, out_of_range)) = accept_payload(bytes, sender, cfg, state) else {
+        state.lock().unwrap().packets_rejected += 1;
+        return;
+    };
+    let blink_reading = cfg.reading.batch.combine(&packet.readings);
+    let Packet {
+        role,
+        swarm_id,
+        readings,
+        seq,
+    } = packet;
 
-    let sock_send = sock.try_clone().context("Failed to clone UDP socket")?;
+    // Log to file (keep behavior) and count the readings in the swarm's stats
+    let raw = format_raw_payload(cfg.log.raw_payload, bytes);
+    let mut blink_ms = Vec::with_capacity(readings.len());
+    let (queued, loss, stuck) = {
+        let mut st = state.lock().unwrap();
+        let ts_ms = st.ts_ms();
+        let loss = seq.and_then(|seq| Some((ts_ms, seq, st.note_seq(&swarm_id, seq)?)));
+        let (mut queued, mut stuck) = (true, None);
+        for (&reading, &out_of_range) in readings.iter().zip(&out_of_range) {
+            let interval = blink_interval(&st.blink_map, reading);
+            let interval_ms = interval.as_millis() as u64;
+            blink_ms.push(interval_ms);
+            st.record_reading(&swarm_id, reading);
+            if let Some(repeats) = st.note_repeat(&swarm_id, reading) {
+                stuck = Some((ts_ms, reading, repeats));
+            }
+            let record_seq = cfg.log.record_seq.then(|| st.next_log_record_seq());
+            // queued under the lock, so records reach the file in record_seq order
+            let appended = log.append(LogRecord {
+                time: Utc::now(),
+                record_seq,
+                role: role.clone(),
+                swarm_id: swarm_id.clone(),
+                reading,
+                sender,
+                raw: raw.clone(),
+                out_of_range,
+                blink: cfg
+                    .log
+                    .blink_fields
+                    .then(|| (interval_ms, st.swarm_to_led.get(&swarm_id).copied())),
+            });
+            if appended {
+                st.log_queue_full = false;
+            } else {
+                st.log_records_dropped += 1;
+                if !std::mem::replace(&mut st.log_queue_full, true) {
+                    warn!("[{ts_ms}] WARN log_queue_full  readings are not logged until it drains");
+                }
+            }
+            queued &= appended;
+        }
+        (queued, loss, stuck)
+    };
+    // Sequence gap: packets from this swarm were lost on the way
+    if let Some((ts_ms, seq, (missing, lost_total))) = loss {
+        let event = Event::PacketLoss {
+            ts_ms,
+            swarm_id: swarm_id.clone(),
+            seq,
+            missing,
+            lost_total,
+        };
+        emit(cfg.status.output, &event);
+    }
+    // Same reading over and over: the sensor has likely failed
+    if let Some((ts_ms, reading, repeats)) = stuck {
+        let event = Event::SensorStuck {
+            ts_ms,
+            swarm_id: swarm_id.clone(),
+            reading,
+            repeats,
+        };
+        emit(cfg.status.output, &event);
+    }
+    // never back to where it came from, so two Pis forwarding to each other can't loop
+    let forward = sinks.forward.as_ref().filter(|_| role == Role::Master);
+    let forward = forward.filter(|f| f.target.ip() != sender.ip());
+    for (&reading, &blink_ms) in readings.iter().zip(&blink_ms) {
+        if let Some(mqtt) = &sinks.mqtt {
+            mqtt.reading(&swarm_id, reading);
+        }
+        if let Some(influx) = &sinks.influx {
+            influx.send(&swarm_id, reading, blink_ms);
+        }
+        if let Some(forward) = forward {
+            let origin = cfg.network.forward_origin.as_deref();
+            let inner = forward_inner(origin, &swarm_id, reading);
+            forward.send(outgoing_frame(&inner, &cfg.network));
+        }
+    }
+    if let Some(to) = ack.filter(|_| queued) {
+        let last = readings[readings.len() - 1];
+        let ack = ack_frame(&swarm_id, last, cfg.network.markers());
+        let sent = match to {
+            AckTo::Udp(sock) => sock.send_to(ack.as_bytes(), sender).map(drop),
+            AckTo::Tcp(mut stream) => stream.write_all(ack.as_bytes()),
+        };
+        if let Err(e) = sent {
+            warn!("ACK to {sender} failed: {e}");
+        }
+    }
+    // Slave (and other) readings are logged and counted, but never take over the LEDs
+    if role == Role::Master {
+        update_master(swarm_id, blink_reading, sender, sinks, cfg, state);
+    }
+}
 
-    // ===== Shared state =====
-    let reset_flag = Arc::new(AtomicBool::new(false));
-    let state = Arc::new(Mutex::new(SharedState::new()));
+// gpio.led_map_file: keep the assignments for the next run (a failure only costs persistence).
+fn save_led_map(cfg: &Config, map: &HashMap<String, usize>) {
+    if let Some(path) = &cfg.gpio.led_map_file {
+        if let Err(e) = ledmap::save(path, map) {
+            warn!("LED map not saved: {e:#}");
+        }
+    }
+}
 
-    // ===== GPIO command channel =====
-    let (tx, rx) = mpsc::channel::<GpioCmd>();
+// Master selection for an accepted reading: hysteresis, flap detection and the state the blink
+// timer and GPIO thread act on, plus the master events.
+fn update_master(
+    swarm_id: String,
+    reading: f64,
+    sender: SocketAddr,
+    sinks: &Sinks,
+    cfg: &Config,
+    state: &Mutex<SharedState>,
+) {
+    // Hysteresis: a new swarm only takes over once confirmed (master.confirm_*).
+    // Flap detection: while stabilizing, switches away from the held master are dropped
+    let (ts_ms, flap_started, flap_exited, held) = {
+        let mut st = state.lock().unwrap();
+        let now = Instant::now();
+        // multi-master mode: its LED blinks whether or not it is (or becomes) the Master
+        st.note_blinking_master(&swarm_id, reading, now);
+        if std::mem::take(&mut st.led_map_changed) {
+            save_led_map(cfg, &st.swarm_to_led);
+        }
+        let flap_exited = st.flap_subsided(now);
+        let switching = st.last_master_id.as_ref().is_some_and(|id| id != &swarm_id);
+        let pending = !st.confirm_master(&swarm_id, now);
+        let flap_started = if switching && !pending {
+            st.flap_check(now)
+        } else {
+            None
+        };
+        let held = pending || (switching && st.stabilize_until.is_some());
+        (st.ts_ms(), flap_started, flap_exited, held)
+    };
+    if flap_exited {
+        info!("[{ts_ms}] EVENT master_stable  stabilization=off");
+    }
+    if let Some(changes) = flap_started {
+        warn!(
+            "[{ts_ms}] WARN master_flapping  changes={changes}  window={}ms  hold={}ms",
+            cfg.flap.window_ms, cfg.flap.hold_ms
+        );
+    }
+    if held {
+        return;
+    }
 
-    // ===== GPIO thread owns ALL gpio handles =====
-    let reset_flag_gpio = Arc::clone(&reset_flag);
-    let state_gpio = Arc::clone(&state);
+    // Update state once and compute everything needed for terminal output
+    let (ts_ms, led_index, interval, master_changed, status_due, prev_master, prev_sender, alarm) = {
+        let mut st = state.lock().unwrap();
 
-    let _gpio_thread = thread::spawn(move || -> Result<()> {
-        let mut chip = open_chip()?;
-        let button = request_input(&mut chip, BUTTON_PIN, "button")?;
-        let white_led = request_output(&mut chip, WHITE_LED_PIN, "white_led", 0)?;
+        let master_changed = match &st.last_master_id {
+            Some(id) => id != &swarm_id,
+            None => true,
+        };
+        // the previous Master and how long it held the role
+        let mut prev_master = None;
+        if master_changed {
+            let now = Instant::now();
+            let dwell = st.end_master_dwell(now).unwrap_or_default();
+            prev_master = st.last_master_id.clone().map(|id| (id, dwell));
+            st.master_since = Some(now);
+            st.master_changes += 1;
+            // a new Master's readings are not averaged with the previous one's
+            st.smoothed_reading.clear();
+            st.alarm = None;
+        }
+        let prev_sender = st.last_master_sender.replace(sender.ip());
+
+        st.last_master_id = Some(swarm_id.clone());
+        st.last_reading = Some(reading);
+        st.last_master_seen = Some(Instant::now());
+        let alarm = st.check_alarm(reading);
 
-        let mut rgb_leds: Vec<LineHandle> = Vec::new();
-        for (i, pin) in RGB_LED_PINS.iter().enumerate() {
-            let h = request_output(&mut chip, *pin, &format!("rgb_led_{i}"), 0)?;
-            rgb_leds.push(h);
+        let led_index = st.assign_led_index(&swarm_id);
+        if std::mem::take(&mut st.led_map_changed) {
+            save_led_map(cfg, &st.swarm_to_led);
         }
+        let smoothed = st.smoothed_reading.push(reading);
+        let interval = blink_interval(&st.blink_map, smoothed);
+        st.active_led = Some(led_index);
+        // reading.stuck_dark: no blinking until the stuck Master's reading changes
+        let dark = cfg.reading.stuck_dark && st.is_stuck(&swarm_id);
+        st.blink_interval = (!dark).then_some(interval);
+
+        // throughput and uptime only matter for the STATUS line
+        let now = Instant::now();
+        let status_due = st
+            .status_due(now)
+            .then(|| (st.packets_per_second(now), st.start.elapsed().as_secs()));
 
-        set_led(&white_led, false);
-        for led in &rgb_leds {
-            set_led(led, false);
+        (
+            st.ts_ms(),
+            led_index,
+            interval,
+            master_changed,
+            status_due,
+            prev_master,
+            prev_sender,
+            alarm,
+        )
+    };
+
+    // terminal output (minimal)
+    let sender = sender.ip();
+    let blink_ms = interval.as_millis() as u64;
+    if master_changed {
+        let event = match prev_master {
+            Some((from, dwell)) => Event::MasterChange {
+                ts_ms,
+                from,
+                swarm_id: swarm_id.clone(),
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+                master_dwell_ms: dwell.as_millis() as u64,
+            },
+            None => Event::MasterSet {
+                ts_ms,
+                swarm_id: swarm_id.clone(),
+                sender,
+                reading,
+                led_index,
+                blink_ms,
+            },
+        };
+        emit(cfg.status.output, &event);
+        if let Some(mqtt) = &sinks.mqtt {
+            mqtt.master(Some(&swarm_id));
         }
+    } else if let Some(prev_sender) = prev_sender.filter(|ip| *ip != sender) {
+        // same master id from a different device: two boards share an id, or one is spoofing it
+        let shown = display_swarm_id(&swarm_id);
+        warn!("[{ts_ms}] WARN master_sender_change  id={shown}  from={prev_sender}  to={sender}");
+    }
 
-        let mut prev_btn = 0;
+    if let Some((bound, threshold)) = alarm {
+        let event = Event::ThresholdAlarm {
+            ts_ms,
+            swarm_id: swarm_id.clone(),
+            reading,
+            bound,
+            threshold,
+        };
+        emit(cfg.status.output, &event);
+    }
 
-        loop {
-            // process gpio commands
-            while let Ok(cmd) = rx.try_recv() {
-                match cmd {
-                    GpioCmd::AllRgbOff => {
-                        for led in &rgb_leds {
-                            set_led(led, false);
-                        }
+    if let Some((pps, uptime_secs)) = status_due {
+        let event = Event::Status {
+            ts_ms,
+            swarm_id,
+            sender,
+            reading,
+            led_index,
+            blink_ms,
+            pps,
+            uptime_secs,
+        };
+        emit(cfg.status.output, &event);
+    }
+}
+
+// GPIO thread: owns ALL gpio handles, applies the LED commands and watches the reset button.
+// Returns Ok at shutdown; the supervisor restarts it after an error or panic. With `ready`
+// (the first run), whether the lines could be opened is reported there instead, for main.
+fn run_gpio(ctx: &GpioContext, ready: Option<mpsc::Sender<Result<()>>>) -> Result<()> {
+    let GpioContext {
+        cfg,
+        state,
+        reset_flag,
+        remote_reset,
+        shutdown,
+        reset_sock,
+        mqtt,
+        log,
+        rx,
+    } = ctx;
+    // a panicked run leaves the lock poisoned; the receiver itself is still fine
+    let rx = rx.lock().unwrap_or_else(PoisonError::into_inner);
+    // only the first run is startup; supervisor restarts skip the self-test
+    let startup = ready.is_some();
+    let lines = match (open_gpio(ctx), ready) {
+        (Ok(lines), ready) => {
+            if let Some(ready) = ready {
+                let _ = ready.send(Ok(()));
+            }
+            lines
+        }
+        (Err(e), Some(ready)) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+        (Err(e), None) => return Err(e),
+    };
+    let GpioLines {
+        mut button,
+        mut white_led,
+        mut rgb_leds,
+    } = lines;
+    let mut errors = GpioErrors::default();
+
+    errors.check(set_led(&white_led, false));
+    for led in &rgb_leds {
+        errors.check(set_led(led, false));
+    }
+    if startup && cfg.gpio.selftest {
+        errors.check(selftest(&rgb_leds, &white_led));
+        let ts_ms = state.lock().unwrap().ts_ms();
+        let leds = rgb_leds.len() + 1;
+        emit(cfg.status.output, &Event::SelftestDone { ts_ms, leds });
+    }
+
+    // Last commanded state per RGB pin, and the index that is currently allowed on
+    let mut rgb_on = vec![false; rgb_leds.len()];
+    let mut rgb_duty = vec![100; rgb_leds.len()];
+    let mut active_rgb: Option<usize> = None;
+    // multi-master mode: blinking one LED leaves the others alone
+    let exclusive = !cfg.multi_master();
+    // color mode: the color shown while the RGB LED is on
+    let mut active_color: Option<[u8; 3]> = None;
+
+    // White LED indication runs off a deadline so commands keep flowing meanwhile
+    let white_hold = Duration::from_millis(WHITE_LED_MS);
+    let mut white_off_at: Option<Instant> = None;
+    let reset_cooldown = Duration::from_millis(cfg.gpio.reset_cooldown_ms);
+
+    let debounce = Duration::from_millis(cfg.gpio.button_debounce_ms);
+    let long_press = Duration::from_millis(cfg.gpio.long_press_ms);
+    let mut last_press: Option<Instant> = None;
+    // start of the press being held, and the last edge seen (to let bounce settle)
+    let mut press_at: Option<Instant> = None;
+    let mut last_edge = Instant::now();
+    let mut btn_down = button_pressed(&button);
+
+    while !shutdown.load(Ordering::SeqCst) {
+        // process gpio commands
+        while let Ok(cmd) = rx.try_recv() {
+            match cmd {
+                GpioCmd::AllRgbOff => {
+                    for (led, last) in rgb_leds.iter().zip(rgb_on.iter_mut()) {
+                        errors.check(set_led_cached(led, last, false));
+                    }
+                    active_rgb = None;
+                    active_color = None;
+                }
+                GpioCmd::BlinkColor { on, duty } => {
+                    let channels = rgb_leds.iter().zip(rgb_on.iter_mut()).zip(duty);
+                    for ((led, last), duty) in channels {
+                        errors.check(set_led_cached(led, last, on && duty > 0));
                     }
-                    GpioCmd::BlinkRgb { idx, on } => {
-                        if idx < rgb_leds.len() {
-                            for (i, led) in rgb_leds.iter().enumerate() {
+                    active_color = on.then_some(duty);
+                }
+                GpioCmd::BlinkRgb { idx, on, duty } => {
+                    if idx < rgb_leds.len() {
+                        rgb_duty[idx] = duty;
+                        // only clear the other LEDs when the target actually changes
+                        if exclusive && active_rgb != Some(idx) {
+                            for (i, (led, last)) in
+                                rgb_leds.iter().zip(rgb_on.iter_mut()).enumerate()
+                            {
                                 if i != idx {
-                                    set_led(led, false);
+                                    errors.check(set_led_cached(led, last, false));
                                 }
                             }
-                            set_led(&rgb_leds[idx], on);
+                            active_rgb = Some(idx);
                         }
-                    }
-                    GpioCmd::WhiteOnFor3s => {
-                        set_led(&white_led, true);
-                        thread::sleep(Duration::from_secs(3));
-                        set_led(&white_led, false);
+                        let led = &rgb_leds[idx];
+                        errors.check(set_led_cached(led, &mut rgb_on[idx], on));
                     }
                 }
+                GpioCmd::WhiteOnFor3s => {
+                    errors.check(set_led(&white_led, true));
+                    white_off_at = Some(Instant::now() + white_hold);
+                }
+            }
+        }
+
+        // white LED expiry
+        if white_off_at.is_some_and(|t| Instant::now() >= t) {
+            errors.check(set_led(&white_led, false));
+            white_off_at = None;
+        }
+
+        errors.report(state);
+
+        // repeated write failures: drop the handles, then open the chip and request every
+        // line again; if that fails too, the supervisor takes over with its backoff
+        if let Some((failed, error)) = errors.take_reconnect() {
+            // the lines can't be requested again while the old handles hold them
+            drop(GpioLines {
+                button,
+                white_led,
+                rgb_leds,
+            });
+            GpioLines {
+                button,
+                white_led,
+                rgb_leds,
+            } = open_gpio(ctx)?;
+            let ts_ms = state.lock().unwrap().ts_ms();
+            let event = Event::GpioReconnect {
+                ts_ms,
+                errors: failed,
+                error,
+            };
+            emit(cfg.status.output, &event);
+            // the lines start off; the blink timer's next commands light them again
+            rgb_on.fill(false);
+            active_rgb = None;
+            active_color = None;
+            if white_off_at.is_some() {
+                errors.check(set_led(&white_led, true));
+            }
+            press_at = None;
+            btn_down = button_pressed(&button);
+        }
+
+        // button press edge; the wait doubles as the loop's idle time, except while
+        // the Masters' LEDs are being pulsed (edges queue in the kernel meanwhile)
+        let poll = Duration::from_millis(50);
+        let dimmed = |duty: &[u8]| duty.iter().any(|d| (1..100).contains(d));
+        let lit: Vec<u8> = rgb_on
+            .iter()
+            .zip(&rgb_duty)
+            .map(|(&on, &duty)| if on { duty } else { 0 })
+            .collect();
+        let pulsed = match active_color {
+            Some(duty) => dimmed(&duty).then(|| duty.to_vec()),
+            None => dimmed(&lit).then_some(lit),
+        };
+        let levels = match pulsed {
+            Some(duty) => {
+                errors.check(pwm_pulse(&rgb_leds, &duty, poll));
+                wait_for_button(&mut button, Duration::ZERO)
+            }
+            None => wait_for_button(&mut button, poll),
+        };
+        let now = Instant::now();
+        for level in levels {
+            // a new press only counts once the previous one has been released
+            let since_press = last_press.map(|t| now - t);
+            if press_at.is_none() && debounced_press(btn_down, level, since_press, debounce) {
+                press_at = Some(now);
+                last_press = Some(now);
             }
+            btn_down = level;
+            last_edge = now;
+        }
 
-            // button press (assumes v=1 unpressed, v=0 pressed)
-            let v = button.get_value().unwrap_or(0);
-            if v == 0 && prev_btn == 1 {
-                reset_flag_gpio.store(true, Ordering::SeqCst);
+        // released and settled (no bounce for a debounce window): short or long press
+        let released = press_at.filter(|_| !btn_down && now - last_edge >= debounce);
+        let mut press = released.map(|pressed_at| {
+            press_at = None;
+            classify_press(last_edge - pressed_at, long_press)
+        });
+        // a remote reset does everything a long press does
+        if remote_reset.swap(false, Ordering::SeqCst) {
+            press = Some(PressKind::Long);
+        }
+        if let Some(kind) = press {
+            reset_flag.store(true, Ordering::SeqCst);
 
+            if kind == PressKind::Long {
                 // broadcast reset
-                let msg = format!("{RPI_START}RESET_REQUESTED{RPI_END}");
-                let bcast = SocketAddrV4::new(Ipv4Addr::new(255, 255, 255, 255), PORT);
-                let _ = sock_send.send_to(msg.as_bytes(), bcast);
-
-                // clear log + reset state
-                let _ = truncate_log();
-                {
-                    let mut st = state_gpio.lock().unwrap();
-                    println!("[{}] EVENT reset_button  broadcast=RESET  white_led=3s", st.ts_ms());
-                    st.reset();
+                let msg = outgoing_frame(RESET_REQUESTED, &cfg.network);
+                if let Some((sock, reset_to)) = &reset_sock {
+                    let _ = sock.send_to(msg.as_bytes(), reset_to);
                 }
 
-                // LEDs
-                for led in &rgb_leds {
-                    set_led(led, false);
+                // clear log
+                let _ = log.truncate();
+            } else {
+                // write the record held by log.dedup, so the next one starts a new line
+                log.flush();
+            }
+
+            // reset state
+            {
+                let mut st = state.lock().unwrap();
+                let kind = match kind {
+                    PressKind::Short => ResetKind::Soft,
+                    PressKind::Long => ResetKind::Hard,
+                };
+                let ts_ms = st.ts_ms();
+                emit(cfg.status.output, &Event::Reset { ts_ms, kind });
+                st.reset();
+                if kind == ResetKind::Hard {
+                    st.log_cleared();
                 }
-                set_led(&white_led, true);
-                thread::sleep(Duration::from_secs(3));
-                set_led(&white_led, false);
+                st.reset_cooldown_until = Some(Instant::now() + reset_cooldown);
+            }
+            // the receive loop resumes right away; the cooldown keeps stale packets out
+            reset_flag.store(false, Ordering::SeqCst);
+            if let Some(mqtt) = mqtt {
+                mqtt.master(None);
+            }
 
-                reset_flag_gpio.store(false, Ordering::SeqCst);
+            // LEDs
+            for (led, last) in rgb_leds.iter().zip(rgb_on.iter_mut()) {
+                errors.check(set_led_cached(led, last, false));
             }
-            prev_btn = v;
+            active_rgb = None;
+            active_color = None;
+            errors.check(set_led(&white_led, true));
+            white_off_at = Some(Instant::now() + white_hold);
+        }
+    }
+
+    // shutdown: leave every LED off, or (hold) as they are. Either way the line handles are
+    // released when dropped, and the kernel leaves a released line's level to the driver: the
+    // Pi 1-4 one (bcm2835/bcm2711) switches the pin to input, so hold only skips the writes.
+    if cfg.gpio.leds_on_shutdown == ShutdownLeds::Off {
+        for led in rgb_leds.iter().chain([&white_led]) {
+            errors.check(set_led(led, false));
+        }
+    }
+    errors.report(state);
+    Ok(())
+}
+
+// Runs the GPIO thread and restarts it when it fails, backing off exponentially. The receive
+// loop keeps running meanwhile; LED commands queue up until the next run picks them up.
+fn supervise_gpio(ctx: Arc<GpioContext>, ready: mpsc::Sender<Result<()>>) {
+    let mut ready = Some(ready);
+    let mut failures = 0u32;
+    let mut retry_ms = GPIO_RETRY_MS;
+    while !ctx.shutdown.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        let worker = Arc::clone(&ctx);
+        let ready = ready.take();
+        let error = match thread::spawn(move || run_gpio(&worker, ready)).join() {
+            Ok(Ok(())) => break,
+            Ok(Err(e)) => format!("{e:#}"),
+            Err(_) => "GPIO thread panicked".to_string(),
+        };
+        // a run that lasted a while counts as recovered: the backoff starts over
+        if started.elapsed() >= Duration::from_millis(GPIO_RETRY_MAX_MS) {
+            failures = 0;
+            retry_ms = GPIO_RETRY_MS;
+        }
+        failures += 1;
+        // a reset in progress would otherwise pause the receive loop for good
+        ctx.reset_flag.store(false, Ordering::SeqCst);
+
+        let ts_ms = ctx.state.lock().unwrap().ts_ms();
+        if failures >= GPIO_FAULT_AFTER {
+            let event = Event::GpioFault {
+                ts_ms,
+                failures,
+                retry_ms,
+                error,
+            };
+            emit(ctx.cfg.status.output, &event);
+        } else {
+            warn!("[{ts_ms}] WARN gpio_restart  retry_in={retry_ms}ms  error={error}");
+        }
+
+        let until = Instant::now() + Duration::from_millis(retry_ms);
+        while Instant::now() < until && !ctx.shutdown.load(Ordering::SeqCst) {
+            thread::sleep((until - Instant::now()).min(Duration::from_millis(100)));
+        }
+        retry_ms = (retry_ms * 2).min(GPIO_RETRY_MAX_MS);
+    }
+}
+
+// --replay: feed a saved session through the same Master update and GPIO path as live packets,
+// optionally sleeping out the gaps between the original timestamps. Nothing is logged again
+// and MQTT/InfluxDB stay off; frames from a raw capture are validated like received ones.
+fn replay_session(
+    path: &Path,
+    paced: bool,
+    cfg: &Config,
+    state: &Mutex<SharedState>,
+    reset_flag: &AtomicBool,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read replay file {}", path.display()))?;
+    let unknown_sender = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+    let sinks = Sinks::default();
+    let (mut replayed, mut skipped) = (0u64, 0u64);
+    let mut prev_ts: Option<i64> = None;
 
+    let markers = cfg.network.markers();
+    for line in text.lines() {
+        let Some(ReplayLine { ts_ms, item }) = replay::parse_line(line, markers) else {
+            skipped += u64::from(!line.trim().is_empty());
+            continue;
+        };
+        if paced {
+            if let Some(gap) = prev_ts.zip(ts_ms).map(|(prev, ts)| ts - prev) {
+                let until = Instant::now() + Duration::from_millis(gap.max(0) as u64);
+                while Instant::now() < until && !shutdown.load(Ordering::SeqCst) {
+                    thread::sleep((until - Instant::now()).min(Duration::from_millis(100)));
+                }
+            }
+            prev_ts = ts_ms.or(prev_ts);
+        }
+        // pause during a button reset (replayed lines are delayed, not dropped)
+        while reset_flag.load(Ordering::SeqCst) && !shutdown.load(Ordering::SeqCst) {
             thread::sleep(Duration::from_millis(50));
         }
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let (role, swarm_id, readings, sender) = match item {
+            ReplayItem::Reading {
+                role,
+                swarm_id,
+                reading,
+                sender,
+            } => {
+                let sender = sender.map_or(unknown_sender, |ip| SocketAddr::new(ip, 0));
+                (role, swarm_id, vec![reading], sender)
+            }
+            ReplayItem::Frame(frame) => {
+                match accept_payload(frame.as_bytes(), unknown_sender, cfg, state) {
+                    Some((packet, _)) => (
+                        packet.role,
+                        packet.swarm_id,
+                        packet.readings,
+                        unknown_sender,
+                    ),
+                    None => {
+                        skipped += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+        for &reading in &readings {
+            state.lock().unwrap().record_reading(&swarm_id, reading);
+        }
+        if role == Role::Master {
+            let reading = cfg.reading.batch.combine(&readings);
+            update_master(swarm_id, reading, sender, &sinks, cfg, state);
+        }
+        replayed += 1;
+    }
+
+    let ts_ms = state.lock().unwrap().ts_ms();
+    info!("[{ts_ms}] EVENT replay_done  replayed={replayed}  skipped={skipped}");
+    Ok(())
+}
+
+// Text events go through the logger (STATUS at debug, packet loss at warn); JSON events are
+// written to stdout. Either way they also go to the --web-port event stream.
+fn emit(output: OutputFormat, event: &Event) {
+    web::publish(event);
+    #[cfg(feature = "grpc")]
+    grpc::publish(event);
+    match output {
+        OutputFormat::Text if matches!(event, Event::Status { .. }) => debug!("{event}"),
+        OutputFormat::Text if matches!(event, Event::PacketLoss { .. }) => warn!("{event}"),
+        OutputFormat::Text if matches!(event, Event::SensorStuck { .. }) => warn!("{event}"),
+        OutputFormat::Text if matches!(event, Event::Diagnostics { .. }) => warn!("{event}"),
+        OutputFormat::Text if matches!(event, Event::WaitingForGpiochip { .. }) => warn!("{event}"),
+        OutputFormat::Text if matches!(event, Event::GpioFault { .. }) => error!("{event}"),
+        OutputFormat::Text if matches!(event, Event::GpioReconnect { .. }) => warn!("{event}"),
+        OutputFormat::Text if matches!(event, Event::GpioChannelClosed { .. }) => error!("{event}"),
+        OutputFormat::Text => info!("{event}"),
+        OutputFormat::Json => println!("{}", event.to_json()),
+    }
+}
+
+// Answer +++STATUS?*** with the current Master's view; ignored until a Master is set.
+fn reply_status(
+    sock: &mut dyn PacketSource,
+    peer: SocketAddr,
+    markers: Markers,
+    state: &Mutex<SharedState>,
+) {
+    let reply = {
+        let st = state.lock().unwrap();
+        let (Some(id), Some(reading), Some(idx), Some(interval)) = (
+            &st.last_master_id,
+            st.last_reading,
+            st.active_led,
+            st.blink_interval,
+        ) else {
+            return;
+        };
+        status_reply(id, reading, idx, interval.as_millis() as u64, markers)
+    };
+    if let Err(e) = sock.send_to(reply.as_bytes(), peer) {
+        warn!("STATUS reply to {peer} failed: {e}");
+    }
+}
+
+// What the receive loop works with, besides its packet source and the shared state.
+struct ReceiveContext<'a> {
+    cfg: &'a Config,
+    sinks: &'a Sinks,
+    log: &'a LogWriter,
+    // frames submitted over TCP, which share the UDP pipeline
+    frames: &'a mpsc::Receiver<TcpFrame>,
+    reset_flag: &'a AtomicBool,
+    // set for an accepted remote reset, handled by the GPIO thread like a long press
+    remote_reset: &'a AtomicBool,
+}
+
+// One pass of the receive loop: the frames queued over TCP, then one datagram from `source`
+// (or its read timeout). `buf` has one byte more than network.max_datagram_bytes.
+fn receive_once(
+    source: &mut dyn PacketSource,
+    buf: &mut [u8],
+    ctx: &ReceiveContext,
+    state: &Mutex<SharedState>,
+) {
+    let cfg = ctx.cfg;
+    let markers = cfg.network.markers();
+    let max_datagram = cfg.network.max_datagram_bytes;
+    let resetting = || {
+        ctx.reset_flag.load(Ordering::SeqCst)
+            || state.lock().unwrap().in_reset_cooldown(Instant::now())
+    };
+
+    // reset and its cooldown: packets sent before the reset are dropped rather than
+    // repopulating the fresh state (status queries are still answered)
+    // at most a queue's worth per pass: the senders refill it as it drains, and UDP is next
+    let queued = ctx.frames.try_iter().take(TCP_QUEUE);
+    if resetting() {
+        queued.for_each(drop);
+    } else {
+        for frame in queued {
+            let ack = frame.reply.as_deref().map(AckTo::Tcp);
+            handle_payload(&frame.bytes, frame.peer, ack, ctx.sinks, ctx.log, cfg, state);
+        }
+    }
+
+    let received = source.recv_from(buf);
+    // checked once the datagram is in: a reset may have started during the wait, which can
+    // take up to network.read_timeout_ms
+    if resetting() {
+        match received {
+            Ok((n, addr)) if n <= max_datagram && is_status_query(&buf[..n], markers) => {
+                reply_status(source, addr, markers, state);
+            }
+            Ok((_, addr)) => debug!("Dropped a packet from {addr} during the reset"),
+            Err(_) => {}
+        }
+        return;
+    }
+
+    match received {
+        Ok((n, addr)) if n > max_datagram => {
+            let ts_ms = {
+                let mut st = state.lock().unwrap();
+                st.note_received(Instant::now());
+                st.packets_rejected += 1;
+                st.ts_ms()
+            };
+            let ip = addr.ip();
+            warn!("[{ts_ms}] WARN payload_too_large  sender={ip}  limit={max_datagram}B");
+        }
+        Ok((n, addr)) if is_status_query(&buf[..n], markers) => {
+            reply_status(source, addr, markers, state);
+        }
+        Ok((n, addr)) if is_remote_reset(&buf[..n], &cfg.network) => {
+            let (ts_ms, cooling_down) = {
+                let st = state.lock().unwrap();
+                (st.ts_ms(), st.in_reset_cooldown(Instant::now()))
+            };
+            let ip = addr.ip();
+            if cooling_down || ctx.reset_flag.load(Ordering::SeqCst) {
+                // most likely the echo of our own reset broadcast
+                debug!("Reset request from {ip} ignored during a reset");
+            } else if cfg.network.reset_allowed_from(ip) {
+                info!("[{ts_ms}] EVENT remote_reset  sender={ip}");
+                ctx.remote_reset.store(true, Ordering::SeqCst);
+            } else {
+                warn!("[{ts_ms}] WARN remote_reset_denied  sender={ip}");
+            }
+        }
+        Ok((n, addr)) => {
+            let ack = match cfg.network.udp_ack {
+                true => Some(AckTo::Udp(&mut *source)),
+                false => None,
+            };
+            handle_payload(&buf[..n], addr, ack, ctx.sinks, ctx.log, cfg, state);
+        }
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::WouldBlock
+                && e.kind() != std::io::ErrorKind::TimedOut
+                && e.kind() != std::io::ErrorKind::Interrupted
+            {
+                warn!("UDP recv error: {e}");
+            }
+        }
+    }
+}
+
+// SIGHUP: re-read the config file (flags still win) and start over with a fresh state, the way
+// a button reset does. An unreadable or invalid file leaves everything as it was.
+fn reload_config(cli: &Cli, sinks: &Sinks, log: &LogWriter, state: &Mutex<SharedState>) {
+    let loaded = Config::load(&cli.config).map(Option::unwrap_or_default);
+    let new = loaded.and_then(|mut new| {
+        cli.apply(&mut new);
+        new.validate()?;
+        let blink_map = BlinkMap::from_config(&new.blink)?;
+        Ok((new, blink_map))
     });
+    let (new, blink_map) = match new {
+        Ok(new) => new,
+        Err(e) => {
+            warn!("Config reload failed, keeping the current config: {e:#}");
+            return;
+        }
+    };
 
-    // ===== Startup terminal output =====
-    println!("RPI UDP listener on port {PORT}");
-    println!("GPIO: button=BCM{BUTTON_PIN} white=BCM{WHITE_LED_PIN} rgb={:?}", RGB_LED_PINS);
-    println!("Protocol: master packets: +++Master,<id>,<reading>***");
+    // log I/O first and outside the lock (the writer may be syncing to the SD card); records
+    // are only appended from this thread, so none can slip in before the state is reset
+    let truncate = new.log.truncate_on_reload;
+    if !truncate {
+        log.flush();
+    }
+    let log_truncated = truncate
+        && match log.truncate() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{e:#}");
+                false
+            }
+        };
+
+    let (ts_ms, output, restart) = {
+        let mut st = state.lock().unwrap();
+        let old_status = st.cfg.status.clone();
+        let (cfg, restart) = st.cfg.reloaded(new);
+        st.cfg = Arc::new(cfg);
+        st.blink_map = blink_map;
+        st.reset();
+        st.smoothed_reading = MovingAverage::new(st.cfg.blink.smoothing_window);
+        if log_truncated {
+            st.log_cleared();
+        }
+        // changed rates take effect now rather than at the deadline set by the old ones
+        let status = st.cfg.status.clone();
+        if status.roster_ms != old_status.roster_ms {
+            st.next_roster_print = jittered_deadline(status.roster_ms, status.jitter_ms);
+        }
+        if status.diagnostics_ms != old_status.diagnostics_ms {
+            st.next_diagnostics_print = jittered_deadline(status.diagnostics_ms, status.jitter_ms);
+        }
+        if status.heartbeat_ms != old_status.heartbeat_ms {
+            st.next_heartbeat = jittered_deadline(status.heartbeat_ms, status.jitter_ms);
+        }
+        (st.ts_ms(), status.output, restart)
+    };
+    for setting in restart {
+        warn!("Config reload: {setting} changed, restart to apply");
+    }
+    let event = Event::ConfigReload {
+        ts_ms,
+        log_truncated,
+    };
+    emit(output, &event);
+    if let Some(mqtt) = &sinks.mqtt {
+        mqtt.master(None);
+    }
+}
 
-    // ===== UDP receive loop =====
-    let mut buf = [0u8; 1024];
+// Per-swarm reading summary (on shutdown and SIGUSR1).
+fn print_summary(state: &Mutex<SharedState>) {
+    let st = state.lock().unwrap();
+    let ts_ms = st.ts_ms();
+    let drops = st.decode_drops;
+    let stats = st.swarm_stats_at(Instant::now());
+    info!(
+        "[{ts_ms}] EVENT summary  swarms={}  rejected_unknown_swarm={}  bad_utf8={}  bad_format={}  rejected_role={}",
+        st.swarm_stats.len(),
+        st.rejected_unknown_swarm,
+        drops.bad_utf8,
+        drops.bad_format,
+        drops.rejected_role
+    );
+    for line in summary_lines(&stats) {
+        info!("  {line}");
+    }
+}
 
-    loop {
-        if reset_flag.load(Ordering::SeqCst) {
-            thread::sleep(Duration::from_millis(50));
+// Periodic inventory report: expected ids that went quiet, unknown ids that showed up.
+fn report_roster(output: OutputFormat, state: &Mutex<SharedState>) {
+    let event = {
+        let mut st = state.lock().unwrap();
+        let Some(swarms) = st.take_roster(Instant::now()) else {
+            return;
+        };
+        Event::Roster {
+            ts_ms: st.ts_ms(),
+            swarms,
+        }
+    };
+    emit(output, &event);
+}
+
+// Periodic decode drop counts, when they changed since the last line.
+fn report_diagnostics(output: OutputFormat, state: &Mutex<SharedState>) {
+    let event = {
+        let mut st = state.lock().unwrap();
+        let Some(drops) = st.take_diagnostics(Instant::now()) else {
+            return;
+        };
+        Event::Diagnostics {
+            ts_ms: st.ts_ms(),
+            bad_utf8: drops.bad_utf8,
+            bad_format: drops.bad_format,
+            rejected_role: drops.rejected_role,
+        }
+    };
+    emit(output, &event);
+}
+
+// Periodic sign of life: uptime, traffic, the current Master and the drop counters.
+fn heartbeat(st: &SharedState) -> Event {
+    Event::Heartbeat {
+        ts_ms: st.ts_ms(),
+        uptime_secs: st.start.elapsed().as_secs(),
+        packets_received: st.packets_received,
+        master: st.last_master_id.clone(),
+        bad_utf8: st.decode_drops.bad_utf8,
+        bad_format: st.decode_drops.bad_format,
+        rejected_role: st.decode_drops.rejected_role,
+        rejected_unknown_swarm: st.rejected_unknown_swarm,
+        log_records_dropped: st.log_records_dropped,
+    }
+}
+
+fn report_inventory(state: &Mutex<SharedState>) {
+    let mut st = state.lock().unwrap();
+    let Some(missing) = st.take_missing_swarms() else {
+        return;
+    };
+    let ts_ms = st.ts_ms();
+    if !missing.is_empty() {
+        warn!("[{ts_ms}] WARN missing_swarms  ids={}", missing.join(","));
+    }
+    if st.unknown_swarm_packets > 0 {
+        let mut ids: Vec<&str> = st.unknown_swarms.iter().map(String::as_str).collect();
+        ids.sort_unstable();
+        warn!(
+            "[{ts_ms}] WARN unknown_swarm  ids={}  packets={}",
+            ids.join(","),
+            st.unknown_swarm_packets
+        );
+    }
+}
+
+// --calibrate: collect readings for `secs` without logging them, then print each swarm's range
+// and the suggested blink.x1/x2 (the lowest and highest reading seen), optionally written to
+// the config file. Ctrl-C ends the collection early.
+fn calibrate(
+    sock: &UdpSocket,
+    secs: u64,
+    write_to: Option<&Path>,
+    cfg: &Config,
+    state: &Mutex<SharedState>,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    info!("Calibration: collecting readings for {secs}s");
+    let until = Instant::now() + Duration::from_secs(secs);
+    let max_datagram = cfg.network.max_datagram_bytes;
+    let mut buf = vec![0u8; max_datagram + 1];
+    while Instant::now() < until && !shutdown.load(Ordering::SeqCst) {
+        // read timeouts just re-check the deadline
+        let Ok((n, addr)) = sock.recv_from(&mut buf) else {
+            continue;
+        };
+        if n > max_datagram {
             continue;
         }
+        if let Some((packet, _)) = accept_payload(&buf[..n], addr, cfg, state) {
+            let mut st = state.lock().unwrap();
+            for &reading in &packet.readings {
+                st.record_reading(&packet.swarm_id, reading);
+            }
+        }
+    }
 
-        match sock.recv_from(&mut buf) {
-            Ok((n, _addr)) => {
-                let payload = match std::str::from_utf8(&buf[..n]) {
-                    Ok(s) => s,
-                    Err(_) => continue,
-                };
+    let st = state.lock().unwrap();
+    for line in summary_lines(&st.swarm_stats) {
+        info!("  {line}");
+    }
+    let Some((x1, x2)) = suggest_range(&st.swarm_stats) else {
+        bail!("Calibration: no usable range (need readings with at least two different values)");
+    };
+    let (ts_ms, swarms) = (st.ts_ms(), st.swarm_stats.len());
+    info!("[{ts_ms}] EVENT calibration  swarms={swarms}  x1={x1}  x2={x2}");
 
-                let Some((swarm_id, reading)) = parse_message(payload) else {
-                    continue;
-                };
+    if let Some(path) = write_to {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        fs::write(path, with_blink_range(&text, x1, x2)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let path = path.display();
+        info!("Config: wrote blink.x1={x1} blink.x2={x2} to {path}");
+    }
+    Ok(())
+}
 
-                // Log to file (keep behavior)
-                let _ = append_log(&swarm_id, reading);
+// Index of a network interface by name, the scope id of IPv6 link-local addresses.
+fn interface_index(name: &str) -> Result<u32> {
+    let c_name = CString::new(name).context("Invalid interface name")?;
+    // SAFETY: `c_name` is a valid NUL-terminated string that outlives the call
+    match unsafe { libc::if_nametoindex(c_name.as_ptr()) } {
+        0 => bail!("Unknown network interface {name}"),
+        index => Ok(index),
+    }
+}
 
-                // Update state once, compute everything we need, then do GPIO cmd
-                let (ts_ms, led_index, led_label, interval, on, master_changed, status_due, prev_master) =
-                    {
-                        let mut st = state.lock().unwrap();
+// An address on network.port, scoped to `scope_id` when it is IPv6.
+fn scoped(ip: IpAddr, port: u16, scope_id: u32) -> SocketAddr {
+    match ip {
+        IpAddr::V4(ip) => (ip, port).into(),
+        IpAddr::V6(ip) => SocketAddrV6::new(ip, port, 0, scope_id).into(),
+    }
+}
 
-                        let prev_master = st.last_master_id.clone();
-                        let master_changed = match &st.last_master_id {
-                            Some(id) => id != &swarm_id,
-                            None => true,
-                        };
+// The listening socket, also used to send resets (broadcast or multicast), and the address
+// resets are sent to.
+fn bind_udp(cfg: &config::NetworkConfig) -> Result<(UdpSocket, SocketAddr)> {
+    let scope_id = match &cfg.interface {
+        Some(name) => interface_index(name)?,
+        None => 0,
+    };
+    let bind_addr = scoped(cfg.bind_ip(), cfg.port, scope_id);
+    let sock = UdpSocket::bind(bind_addr)
+        .with_context(|| format!("Failed to bind UDP {bind_addr}"))?;
+    match (bind_addr.ip(), cfg.multicast_group) {
+        (IpAddr::V4(interface), group) => {
+            sock.set_broadcast(true).context("Failed to enable broadcast")?;
+            if let Some(IpAddr::V4(group)) = group {
+                sock.join_multicast_v4(&group, &interface)
+                    .with_context(|| format!("Failed to join multicast group {group}"))?;
+                sock.set_multicast_ttl_v4(MULTICAST_TTL)
+                    .context("Failed to set multicast TTL")?;
+            }
+        }
+        // the all-nodes group needs no join; link-local groups never leave the segment
+        (IpAddr::V6(_), Some(IpAddr::V6(group))) => {
+            sock.join_multicast_v6(&group, scope_id)
+                .with_context(|| format!("Failed to join multicast group {group}"))?;
+        }
+        (IpAddr::V6(_), _) => {}
+    }
+    sock.set_read_timeout(Some(Duration::from_millis(cfg.read_timeout_ms)))
+        .context("Failed to set read timeout")?;
+    Ok((sock, scoped(cfg.reset_addr(), cfg.port, scope_id)))
+}
 
-                        st.last_master_id = Some(swarm_id.clone());
-                        st.last_reading = Some(reading);
+fn main() -> Result<()> {
+    // ===== Config (lightswarm.toml, falling back to built-in defaults; flags override) =====
+    let cli = Cli::parse();
+    if cli.list_gpiochips {
+        return list_gpiochips();
+    }
+    let loaded = Config::load(&cli.config)?;
+    let found = loaded.is_some();
+    let mut cfg = loaded.unwrap_or_default();
+    cli.apply(&mut cfg);
+    cfg.validate()?;
+    if cli.dry_run {
+        // nothing outside the process: the log goes to the temp dir, no sinks, no LED map file
+        let name = cfg.log.path.file_name().unwrap_or_default().to_owned();
+        cfg.log.path = std::env::temp_dir().join(name);
+        cfg.gpio.led_map_file = None;
+        cfg.mqtt.host = None;
+        cfg.network.influx_udp = None;
+        cfg.network.forward_to = None;
+        cfg.network.tcp_ack = false;
+    }
 
-                        let led_index = st.assign_led_index(&swarm_id);
-                        let interval = Duration::from_secs_f64(blink_interval_seconds(reading));
+    // ===== Console output: EVENT at info, STATUS at debug (RUST_LOG overrides) =====
+    // With JSON output stdout carries only events, so everything else goes to stderr.
+    let target = match cfg.status.output {
+        OutputFormat::Text => env_logger::Target::Stdout,
+        OutputFormat::Json => env_logger::Target::Stderr,
+    };
+    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+        .target(target)
+        .init();
+    if found {
+        info!("Config: loaded {}", cli.config.display());
+    } else {
+        info!("Config: {} not found, using defaults", cli.config.display());
+    }
+    let blink_map = BlinkMap::from_config(&cfg.blink)?;
+    let cfg = Arc::new(cfg);
+    let port = cfg.network.port;
 
-                        if st.previous_toggle.elapsed() >= interval {
-                            st.previous_toggle = Instant::now();
-                            st.led_state = !st.led_state;
-                        }
-                        let on = st.led_state;
+    // ===== UDP init (none when replaying) =====
+    let (sock, reset_to) = match cli.replay {
+        Some(_) => (None, None),
+        None => {
+            let (sock, reset_to) = bind_udp(&cfg.network)?;
+            (Some(sock), Some(reset_to))
+        }
+    };
+    let sock_send = match (&sock, reset_to) {
+        (Some(sock), Some(reset_to)) => {
+            let sock = sock.try_clone().context("Failed to clone UDP socket")?;
+            Some((sock, reset_to))
+        }
+        _ => None,
+    };
 
-                        let status_due = st.last_status_print.elapsed()
-                            >= Duration::from_millis(STATUS_PRINT_MS);
-                        if status_due {
-                            st.last_status_print = Instant::now();
-                        }
+    // ===== Shared state =====
+    let reset_flag = Arc::new(AtomicBool::new(false));
+    let remote_reset = Arc::new(AtomicBool::new(false));
+
+    // ===== Shutdown on Ctrl-C / SIGTERM: every loop checks this flag =====
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))
+            .context("Failed to install signal handler")?;
+    }
+    // SIGUSR1 prints the per-swarm summary without stopping; SIGHUP reloads the config
+    let summary_requested = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGUSR1, Arc::clone(&summary_requested))
+        .context("Failed to install signal handler")?;
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&reload_requested))
+        .context("Failed to install signal handler")?;
+    let mut st = SharedState::new(Arc::clone(&cfg), blink_map);
+    if let Some(path) = &cfg.gpio.led_map_file {
+        st.restore_led_map(ledmap::load(path)?);
+    }
+    let state = Arc::new(Mutex::new(st));
+
+    // ===== Calibration run (instead of a normal session; GPIO is never opened) =====
+    if let (Some(secs), Some(sock)) = (cli.calibrate, &sock) {
+        let write_to = cli.calibrate_write.then_some(cli.config.as_path());
+        return calibrate(sock, secs, write_to, &cfg, &state, &shutdown);
+    }
+
+    // ===== Sensor log writer =====
+    let log = LogWriter::start(&cfg.log);
+
+    // ===== MQTT publisher (optional) =====
+    let mqtt = cfg.mqtt.host.as_deref().map(|host| {
+        let (port, prefix) = (cfg.mqtt.port, &cfg.mqtt.topic_prefix);
+        info!("MQTT: publishing to {host}:{port} under {prefix}/");
+        Publisher::start(&cfg.mqtt, host)
+    });
 
-                        (
-                            st.ts_ms(),
-                            led_index,
-                            SharedState::led_label(led_index),
-                            interval,
-                            on,
-                            master_changed,
-                            status_due,
-                            prev_master,
-                        )
-                    };
-
-                // terminal output (minimal)
-                if master_changed {
-                    if let Some(prev) = prev_master {
-                        println!(
-                            "[{ts_ms}] EVENT master_change  from={prev}  to={swarm_id}  {led_label}"
-                        );
-                    } else {
-                        println!("[{ts_ms}] EVENT master_set  to={swarm_id}  {led_label}");
+    // ===== InfluxDB line protocol (optional) =====
+    let influx = match cfg.network.influx_udp {
+        Some(addr) => {
+            info!("InfluxDB: sending readings to {addr} over UDP");
+            Some(InfluxSink::open(addr)?)
+        }
+        None => None,
+    };
+    // ===== Forwarding to another host (optional) =====
+    let forward = match (cfg.network.forward_to, &sock) {
+        (Some(target), Some(sock)) => {
+            info!("Forward: relaying readings to {target}");
+            let sock = sock.try_clone().context("Failed to clone UDP socket")?;
+            Some(Forwarder::start(sock, target))
+        }
+        _ => None,
+    };
+    let sinks = Sinks {
+        mqtt: mqtt.clone(),
+        influx,
+        forward,
+    };
+
+    // ===== GPIO thread owns ALL gpio handles, restarted by a supervisor when it fails =====
+    // (none in a dry run: LED commands are dropped, and the button is never read)
+    let (tx, gpio_supervisor) = if cli.dry_run {
+        (None, None)
+    } else {
+        let (tx, rx) = mpsc::channel::<GpioCmd>();
+        let gpio_ctx = Arc::new(GpioContext {
+            cfg: Arc::clone(&cfg),
+            state: Arc::clone(&state),
+            reset_flag: Arc::clone(&reset_flag),
+            remote_reset: Arc::clone(&remote_reset),
+            shutdown: Arc::clone(&shutdown),
+            reset_sock: sock_send,
+            mqtt: mqtt.clone(),
+            log: log.clone(),
+            rx: Mutex::new(rx),
+        });
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let gpio_supervisor = thread::spawn(move || supervise_gpio(gpio_ctx, ready_tx));
+        // no LED control is a startup error, not something to find out later
+        match ready_rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                return Err(e.context(
+                    "GPIO startup failed (check the gpio pins and access to /dev/gpiochip*, or run with --simulate)",
+                ))
+            }
+            Err(_) => bail!("GPIO thread exited during startup"),
+        }
+        (Some(tx), Some(gpio_supervisor))
+    };
+
+    // ===== Blink timer thread owns the toggle state, independent of packet arrival =====
+    let state_timer = Arc::clone(&state);
+    let shutdown_timer = Arc::clone(&shutdown);
+    let timer_gpio_cfg = cfg.gpio.clone();
+    let timer_master_cfg = cfg.master.clone();
+    let timer_output = cfg.status.output;
+    let multi_master = cfg.multi_master();
+    let mqtt_timer = mqtt.clone();
+    // set by the timer when the GPIO thread can no longer be reached; main then exits with an error
+    let gpio_lost = Arc::new(AtomicBool::new(false));
+    let gpio_lost_timer = Arc::clone(&gpio_lost);
+    thread::spawn(move || {
+        // false once the GPIO thread is gone; a dry run drops every command
+        let send = |cmd: GpioCmd| tx.as_ref().is_none_or(|tx| tx.send(cmd).is_ok());
+        // toggle state per blinking LED: (on, previous toggle)
+        let mut blinking: HashMap<usize, (bool, Option<Instant>)> = HashMap::new();
+        let max_tick = Duration::from_millis(BLINK_TICK_MS);
+
+        'timer: while !shutdown_timer.load(Ordering::SeqCst) {
+            let (targets, color, stale, alarm_flash, heartbeat) = {
+                let mut st = state_timer.lock().unwrap();
+                let now = Instant::now();
+                let stale = st.take_stale_master(now).map(|id| (st.ts_ms(), id));
+                let heartbeat = st.heartbeat_due(now).then(|| heartbeat(&st));
+                let alarm_flash = std::mem::take(&mut st.alarm_flash);
+                let others = st.other_masters(now);
+                let color = match timer_gpio_cfg.rgb {
+                    RgbMode::Color => st.last_reading.map(|r| reading_color(&st.blink_map, r)),
+                    RgbMode::Swarm => {
+                        let master = st.last_master_id.clone();
+                        master.map(|id| st.swarm_color(&id).duty())
+                    }
+                    RgbMode::Single => None,
+                };
+                let duty = |reading: Option<f64>| match (timer_gpio_cfg.pwm, reading) {
+                    (PwmMode::On, Some(reading)) => {
+                        brightness_duty(&st.blink_map, reading, timer_gpio_cfg.pwm_min_duty)
                     }
+                    _ => 100,
+                };
+                // (LED, interval, duty): the current Master's, then any other recent Masters'
+                let current = st.active_led.zip(st.blink_interval);
+                let current = current.map(|(idx, interval)| (idx, interval, duty(st.last_reading)));
+                let others = others
+                    .into_iter()
+                    .map(|(idx, r, i)| (idx, i, duty(Some(r))));
+                let targets: Vec<(usize, Duration, u8)> =
+                    current.into_iter().chain(others).collect();
+                (targets, color, stale, alarm_flash, heartbeat)
+            };
+            // printed here rather than by the receive loop, so it keeps coming on a silent network
+            if let Some(event) = heartbeat {
+                emit(timer_output, &event);
+            }
+            // a new threshold alarm: the white LED, off again after WHITE_LED_MS
+            if alarm_flash && !send(GpioCmd::WhiteOnFor3s) {
+                break;
+            }
+            if let Some((ts_ms, swarm_id)) = stale {
+                let event = Event::MasterTimeout {
+                    ts_ms,
+                    swarm_id,
+                    silent_ms: timer_master_cfg.timeout_ms,
+                };
+                emit(timer_output, &event);
+                if let Some(mqtt) = &mqtt_timer {
+                    mqtt.master(None);
                 }
+                // other Masters keep blinking; only the stale one's LED goes off below
+                if !multi_master && !send(GpioCmd::AllRgbOff) {
+                    break;
+                }
+            }
+            if targets.is_empty() {
+                // no master (startup / reset) or a dark stuck one: forget the toggle state
+                if !blinking.is_empty() && !send(GpioCmd::AllRgbOff) {
+                    break;
+                }
+                blinking.clear();
+                thread::sleep(max_tick);
+                continue;
+            }
 
-                if status_due {
-                    let ms = interval.as_millis();
-                    println!(
-                        "[{ts_ms}] STATUS master={swarm_id} value={reading} blink={ms}ms {led_label}"
-                    );
+            // LEDs no longer blinking: off (with a single Master, blinking the new LED
+            // turns the previous one off)
+            let stopped: Vec<usize> = blinking
+                .keys()
+                .filter(|idx| !targets.iter().any(|(i, ..)| i == *idx))
+                .copied()
+                .collect();
+            for idx in stopped {
+                blinking.remove(&idx);
+                let off = GpioCmd::BlinkRgb {
+                    idx,
+                    on: false,
+                    duty: 100,
+                };
+                if multi_master && !send(off) {
+                    break 'timer;
                 }
+            }
 
-                // Drive RGB LED
-                let _ = tx.send(GpioCmd::BlinkRgb {
-                    idx: led_index,
-                    on,
-                });
+            // a new LED starts its blink with an immediate ON
+            let mut wait = max_tick;
+            for (idx, interval, duty) in targets {
+                let (led_on, previous_toggle) = blinking.entry(idx).or_insert((false, None));
+                match previous_toggle.map(|t| t.elapsed()) {
+                    Some(elapsed) if elapsed < interval => {
+                        wait = wait.min(interval - elapsed);
+                    }
+                    _ => {
+                        *previous_toggle = Some(Instant::now());
+                        *led_on = !*led_on;
+                        let cmd = match color {
+                            Some(duty) => GpioCmd::BlinkColor { on: *led_on, duty },
+                            None => GpioCmd::BlinkRgb {
+                                idx,
+                                on: *led_on,
+                                duty,
+                            },
+                        };
+                        if !send(cmd) {
+                            break 'timer;
+                        }
+                        wait = wait.min(interval);
+                    }
+                }
             }
-            Err(e) => {
-                if e.kind() != std::io::ErrorKind::WouldBlock
-                    && e.kind() != std::io::ErrorKind::TimedOut
-                {
-                    eprintln!("UDP recv error: {e}");
+            thread::sleep(wait);
+        }
+        // the loop only ends early when a send failed: the GPIO supervisor is gone (it stops
+        // only at shutdown or by panicking) and no restart will pick the commands up again
+        if !shutdown_timer.load(Ordering::SeqCst) {
+            let ts_ms = state_timer.lock().unwrap().ts_ms();
+            emit(timer_output, &Event::GpioChannelClosed { ts_ms });
+            gpio_lost_timer.store(true, Ordering::SeqCst);
+            shutdown_timer.store(true, Ordering::SeqCst);
+        }
+    });
+
+    // ===== TCP ingest listener =====
+    let (frames_tx, frames_rx) = mpsc::sync_channel::<TcpFrame>(TCP_QUEUE);
+    if let Some(tcp_port) = cfg.network.tcp_ingest_port {
+        let listener = TcpListener::bind(SocketAddr::new(cfg.network.any_ip(), tcp_port))
+            .with_context(|| format!("Failed to bind TCP port {tcp_port}"))?;
+        let tcp_port = listener.local_addr().map_or(tcp_port, |addr| addr.port());
+        info!("RPI TCP ingest on port {tcp_port}");
+        let net_cfg = Arc::new(cfg.network.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let Some(slot) = TCP_SLOTS.take() else {
+                            let peer = stream
+                                .peer_addr()
+                                .map_or_else(|_| "?".to_string(), |addr| addr.to_string());
+                            debug!("TCP ingest: closed {peer}, {TCP_MAX_CONNS} connections open");
+                            continue;
+                        };
+                        let frames = frames_tx.clone();
+                        let net_cfg = Arc::clone(&net_cfg);
+                        thread::spawn(move || {
+                            let _slot = slot;
+                            if let Err(e) = serve_tcp_conn(stream, frames, &net_cfg) {
+                                warn!("TCP connection error: {e:#}");
+                            }
+                        });
+                    }
+                    Err(e) => warn!("TCP accept error: {e}"),
                 }
             }
+        });
+    }
+
+    // ===== Prometheus metrics (optional) =====
+    if let Some(metrics_port) = cfg.network.metrics_port {
+        let addr = SocketAddr::new(cfg.network.any_ip(), metrics_port);
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow!("Failed to bind metrics port {metrics_port}: {e}"))?;
+        info!("Metrics on port {metrics_port} (/metrics)");
+        let state_metrics = Arc::clone(&state);
+        let shutdown_metrics = Arc::clone(&shutdown);
+        thread::spawn(move || serve_metrics(&server, &state_metrics, &shutdown_metrics));
+    }
+
+    // ===== Live event stream (optional) =====
+    if let Some(web_port) = cfg.network.web_port {
+        let addr = SocketAddr::new(cfg.network.any_ip(), web_port);
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow!("Failed to bind web port {web_port}: {e}"))?;
+        info!("Web page on port {web_port} (/, events on /events)");
+        let state_web = Arc::clone(&state);
+        let shutdown_web = Arc::clone(&shutdown);
+        thread::spawn(move || web::serve(&server, &state_web, &shutdown_web));
+    }
+
+    // ===== gRPC event stream (optional) =====
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = cfg.network.grpc_port {
+        let addr = SocketAddr::new(cfg.network.any_ip(), grpc_port);
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| anyhow!("Failed to bind gRPC port {grpc_port}: {e}"))?;
+        info!("gRPC event stream on port {grpc_port} (lightswarm.EventFeed/Subscribe)");
+        let state_grpc = Arc::clone(&state);
+        let shutdown_grpc = Arc::clone(&shutdown);
+        thread::spawn(move || grpc::serve(listener, state_grpc, shutdown_grpc));
+    }
+
+    // ===== Control console (stdin) =====
+    let state_ctl = Arc::clone(&state);
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let mut st = state_ctl.lock().unwrap();
+            if let Err(e) = apply_control_command(&mut st, &line) {
+                warn!("Control error: {e:#}");
+            }
+        }
+    });
+
+    // ===== Startup terminal output =====
+    // the bound address: with network.port 0 the OS picks a free port
+    let bind_addr = match &sock {
+        Some(sock) => sock.local_addr().context("Failed to read UDP address")?,
+        None => SocketAddr::new(cfg.network.bind_ip(), port),
+    };
+    info!("raspberrypi {}", cli::VERSION);
+    match &cli.replay {
+        Some(path) => info!("Replay: {} (no sockets bound)", path.display()),
+        None => info!("RPI UDP listener on {bind_addr}"),
+    }
+    let per_swarm = match cfg.log.per_swarm {
+        true => " (one sensor_<id> file per swarm beside it)",
+        false => "",
+    };
+    info!(
+        "Log: {}{per_swarm}  reset to: {}",
+        cfg.log.path.display(),
+        cfg.network.reset_addr()
+    );
+    info!(
+        "GPIO: button=BCM{} white=BCM{} rgb={:?}",
+        cfg.gpio.button_pin, cfg.gpio.white_led_pin, cfg.gpio.rgb_led_pins
+    );
+    if cli.dry_run {
+        info!("Dry run: no GPIO, MQTT, InfluxDB, forwarding, resets, ACKs or STATUS replies");
+    } else if cfg.gpio.simulate {
+        info!("GPIO: simulated, LED writes are printed as set_led lines");
+    }
+    let markers = cfg.network.markers();
+    match cfg.network.protocol {
+        WireProtocol::Text => info!(
+            "Protocol: master packets: {}",
+            markers.frame("Master,<id>,<reading>")
+        ),
+        WireProtocol::Binary => info!(
+            "Protocol: binary, {BINARY_FRAME_LEN}-byte packets: <type 0x{BINARY_MASTER:02x}=Master \
+             0x{BINARY_SLAVE:02x}=Slave><id, {BINARY_ID_LEN} bytes NUL-padded><reading, i32 LE>"
+        ),
+    }
+    if cfg.network.aggregator_mode && cfg.network.protocol == WireProtocol::Text {
+        let forwarded = markers.frame(&format!("{FORWARD_ROLE},<origin>,<id>,<reading>"));
+        info!("Aggregator: forwarded packets: {forwarded}");
+    }
+    info!("Control: set-map <x1> <y1> <x2> <y2> | set-blink-bounds <min_ms> <max_ms> | show-map");
+
+    // ===== Replay: the saved session stands in for the network =====
+    if let Some(path) = &cli.replay {
+        replay_session(path, cli.replay_pace, &cfg, &state, &reset_flag, &shutdown)?;
+        // the end of the file ends the session
+        shutdown.store(true, Ordering::SeqCst);
+    }
+
+    if let Some(sock) = sock {
+        // ===== UDP receive loop =====
+        // a dry run receives as usual but sends no ACKs or STATUS replies
+        let mut source: Box<dyn PacketSource> = match cli.dry_run {
+            true => Box::new(ReceiveOnly(sock)),
+            false => Box::new(sock),
+        };
+        // one spare byte: a datagram that fills it is larger than the limit (and was truncated)
+        let mut buf = vec![0u8; cfg.network.max_datagram_bytes + 1];
+        let ctx = ReceiveContext {
+            cfg: &cfg,
+            sinks: &sinks,
+            log: &log,
+            frames: &frames_rx,
+            reset_flag: &reset_flag,
+            remote_reset: &remote_reset,
+        };
+
+        while !shutdown.load(Ordering::SeqCst) {
+            receive_once(source.as_mut(), &mut buf, &ctx, &state);
+
+            report_inventory(&state);
+            report_roster(cfg.status.output, &state);
+            report_diagnostics(cfg.status.output, &state);
+            if summary_requested.swap(false, Ordering::SeqCst) {
+                print_summary(&state);
+            }
+            if reload_requested.swap(false, Ordering::SeqCst) {
+                reload_config(&cli, &sinks, &log, &state);
+            }
         }
     }
+
+    // ===== Shutdown =====
+    info!("[{}] EVENT shutdown", state.lock().unwrap().ts_ms());
+    log.flush();
+    print_summary(&state);
+    if gpio_supervisor.is_some_and(|supervisor| supervisor.join().is_err()) {
+        error!("GPIO supervisor panicked");
+    }
+    if gpio_lost.load(Ordering::SeqCst) {
+        // a non-zero exit status lets a service manager restart the program
+        bail!("GPIO control lost (gpio_channel_closed)");
+    }
+    Ok(())
 }
@@ -0,0 +1,96 @@
+// Prometheus text exposition of the shared state, served on /metrics.
+
+use crate::state::SharedState;
+use std::fmt::Write;
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: Option<f64>) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    if let Some(value) = value {
+        let _ = writeln!(out, "{name} {value}");
+    }
+}
+
+// Gauges without a current Master are left without a sample.
+pub fn render(st: &SharedState) -> String {
+    let mut out = String::new();
+    metric(
+        &mut out,
+        "lightswarm_packets_received_total",
+        "counter",
+        "Frames received over UDP and TCP.",
+        Some(st.packets_received as f64),
+    );
+    metric(
+        &mut out,
+        "lightswarm_packets_rejected_total",
+        "counter",
+        "Frames dropped before logging (malformed, unauthenticated, not allowed or out of range).",
+        Some(st.packets_rejected as f64),
+    );
+    metric(
+        &mut out,
+        "lightswarm_log_records_dropped_total",
+        "counter",
+        "Accepted readings not logged because the log writer fell behind.",
+        Some(st.log_records_dropped as f64),
+    );
+    metric(
+        &mut out,
+        "lightswarm_gpio_write_errors_total",
+        "counter",
+        "LED writes that failed on the GPIO line.",
+        Some(st.gpio_write_errors as f64),
+    );
+    metric(
+        &mut out,
+        "lightswarm_master_changes_total",
+        "counter",
+        "Times a swarm became Master.",
+        Some(st.master_changes as f64),
+    );
+    metric(
+        &mut out,
+        "lightswarm_current_reading",
+        "gauge",
+        "Latest reading of the current Master.",
+        st.last_reading,
+    );
+    metric(
+        &mut out,
+        "lightswarm_blink_interval_ms",
+        "gauge",
+        "Blink interval of the current Master's LED.",
+        st.blink_interval.map(|d| d.as_millis() as f64),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blink::BlinkMap;
+    use crate::config::{BlinkConfig, Config};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn counters_and_gauges() {
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(Config::default()), blink_map);
+        st.packets_received = 5;
+        let text = render(&st);
+        assert!(text.contains("# TYPE lightswarm_packets_received_total counter\n"));
+        assert!(text.contains("\nlightswarm_packets_received_total 5\n"));
+        assert!(text.contains("\nlightswarm_log_records_dropped_total 0\n"));
+        assert!(text.contains("\nlightswarm_gpio_write_errors_total 0\n"));
+        // no Master yet: no sample
+        assert!(!text.contains("\nlightswarm_current_reading "));
+
+        st.last_reading = Some(512.0);
+        st.blink_interval = Some(Duration::from_millis(1058));
+        let text = render(&st);
+        assert!(text.contains("\nlightswarm_current_reading 512\n"));
+        assert!(text.contains("\nlightswarm_blink_interval_ms 1058\n"));
+    }
+}
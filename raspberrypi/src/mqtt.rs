@@ -0,0 +1,75 @@
+use log::{info, warn};
+use raspberrypi::config::MqttConfig;
+use rumqttc::{Client, MqttOptions, QoS};
+use std::thread;
+use std::time::Duration;
+
+// Queued publishes before new ones are dropped (the broker is slow or unreachable).
+const MQTT_QUEUE: usize = 64;
+// Pause before reconnecting after a connection error.
+const MQTT_RETRY_MS: u64 = 2000;
+
+// Publishes readings and the current Master. Publishing only queues the message; a
+// background thread owns the connection, so a slow broker never stalls the UDP loop.
+#[derive(Clone)]
+pub struct Publisher {
+    client: Client,
+    prefix: String,
+}
+
+impl Publisher {
+    pub fn start(cfg: &MqttConfig, host: &str) -> Self {
+        let mut options = MqttOptions::new(&cfg.client_id, host, cfg.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, MQTT_QUEUE);
+
+        let broker = format!("{host}:{}", cfg.port);
+        thread::spawn(move || {
+            let mut connected = false;
+            for notification in connection.iter() {
+                match notification {
+                    Ok(_) if !connected => {
+                        connected = true;
+                        info!("MQTT: connected to {broker}");
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if connected {
+                            warn!("MQTT: connection to {broker} lost: {e}");
+                        }
+                        connected = false;
+                        // the next iteration reconnects
+                        thread::sleep(Duration::from_millis(MQTT_RETRY_MS));
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            prefix: cfg.topic_prefix.clone(),
+        }
+    }
+
+    fn publish(&self, topic: String, retain: bool, payload: String) {
+        // a full queue drops the message instead of blocking
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, retain, payload)
+        {
+            warn!("MQTT: publish dropped: {e}");
+        }
+    }
+
+    // <prefix>/<swarm_id>/reading
+    pub fn reading(&self, swarm_id: &str, reading: f64) {
+        let topic = format!("{}/{swarm_id}/reading", self.prefix);
+        self.publish(topic, false, reading.to_string());
+    }
+
+    // <prefix>/master (retained); empty once there is no Master, which clears it
+    pub fn master(&self, swarm_id: Option<&str>) {
+        let topic = format!("{}/master", self.prefix);
+        self.publish(topic, true, swarm_id.unwrap_or_default().to_string());
+    }
+}
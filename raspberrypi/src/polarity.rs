@@ -0,0 +1,36 @@
+// Line levels for LEDs and the button, which may be wired either way round.
+
+// Level to drive an LED line to: active-high LEDs light at 1, active-low ones at 0.
+pub fn led_level(on: bool, active_low: bool) -> u8 {
+    u8::from(on != active_low)
+}
+
+// Whether a button line level means pressed: an active-low button reads 0 while pressed.
+pub fn button_down(level: u8, active_low: bool) -> bool {
+    (level == 0) == active_low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn led_levels() {
+        // default wiring: active-high
+        assert_eq!(led_level(true, false), 1);
+        assert_eq!(led_level(false, false), 0);
+        // through an inverting transistor
+        assert_eq!(led_level(true, true), 0);
+        assert_eq!(led_level(false, true), 1);
+    }
+
+    #[test]
+    fn button_levels() {
+        // default wiring: active-low (button to GND, pull-up)
+        assert!(button_down(0, true));
+        assert!(!button_down(1, true));
+        // button pulling the line high
+        assert!(button_down(1, false));
+        assert!(!button_down(0, false));
+    }
+}
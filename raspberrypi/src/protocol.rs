@@ -0,0 +1,686 @@
+// Wire format of frames received from the swarm (and forwarded by other Pis).
+
+use std::fmt;
+
+// ===== UDP / Protocol =====
+// Default frame markers (network.start_marker / end_marker)
+pub const RPI_START: &str = "+++";
+pub const RPI_END: &str = "***";
+pub const DEFAULT_MARKERS: Markers<'static> = Markers {
+    start: RPI_START,
+    end: RPI_END,
+};
+// Role of the swarm's Master frames
+pub const MASTER_ROLE: &str = "Master";
+// Role of readings from swarm members that are not the Master
+pub const SLAVE_ROLE: &str = "Slave";
+// Role of the Pi's own acknowledgements, never taken for a reading
+pub const ACK_ROLE: &str = "ACK";
+// Role of frames forwarded by other Pis (accepted in aggregator mode)
+pub const FORWARD_ROLE: &str = "Forward";
+// Sent by the Pi on a hard reset (the swarm re-initializes)
+pub const RESET_REQUESTED: &str = "RESET_REQUESTED";
+// Query for the Pi's current view, answered with status_reply()
+pub const STATUS_QUERY: &str = "STATUS?";
+
+// Binary frames (network.protocol = "binary"): <type><swarm_id><reading>
+pub const BINARY_ID_LEN: usize = 8;
+pub const BINARY_FRAME_LEN: usize = 1 + BINARY_ID_LEN + 4;
+pub const BINARY_MASTER: u8 = 0x01;
+pub const BINARY_SLAVE: u8 = 0x02;
+
+// Start and end markers around every frame, received or sent. Frames are shown with the
+// default +++ and *** throughout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Markers<'a> {
+    pub start: &'a str,
+    pub end: &'a str,
+}
+
+impl Markers<'_> {
+    // +++<inner>***
+    pub fn frame(&self, inner: &str) -> String {
+        format!("{}{inner}{}", self.start, self.end)
+    }
+
+    // What is between the markers, or None for a payload they don't frame.
+    pub fn inner<'p>(&self, payload: &'p str) -> Option<&'p str> {
+        payload.strip_prefix(self.start)?.strip_suffix(self.end)
+    }
+}
+
+// Role named in a reading's first field. Every role is logged; only Master readings select
+// the Master and drive the LEDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Role {
+    Master,
+    Slave,
+    // any other (alphabetic) role name, handled like Slave
+    Other(String),
+}
+
+impl Role {
+    // None for names that never start a reading (Forward, ACK) or are not a word.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            MASTER_ROLE => Some(Self::Master),
+            SLAVE_ROLE => Some(Self::Slave),
+            FORWARD_ROLE | ACK_ROLE => None,
+            _ if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()) => {
+                Some(Self::Other(name.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Master => f.write_str(MASTER_ROLE),
+            Self::Slave => f.write_str(SLAVE_ROLE),
+            Self::Other(name) => f.write_str(name),
+        }
+    }
+}
+
+// One accepted frame: a reading, or a batch of the sender's samples (oldest first)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub role: Role,
+    pub swarm_id: String,
+    // never empty
+    pub readings: Vec<f64>,
+    // Sender's sequence number, when the firmware sends one (for packet loss detection)
+    pub seq: Option<u64>,
+}
+
+impl Packet {
+    fn new(
+        role: Role,
+        swarm_id: String,
+        readings: &[&str],
+        seq: Option<&str>,
+    ) -> Result<Self, ParseError> {
+        Ok(Self {
+            role,
+            swarm_id,
+            readings: readings
+                .iter()
+                .map(|r| parse_reading(r).ok_or(ParseError::Format))
+                .collect::<Result<_, _>>()?,
+            seq: seq
+                .map(str::parse)
+                .transpose()
+                .map_err(|_| ParseError::Format)?,
+        })
+    }
+
+    // The latest sample (the only one, unless the frame is a batch)
+    pub fn last_reading(&self) -> f64 {
+        self.readings[self.readings.len() - 1]
+    }
+}
+
+// Readings are plain decimals, "512" or "512.75" (an optional sign, no exponent); NaN,
+// infinity and "1e3" are not readings. Integers are parsed exactly as before, and a float is
+// logged as sent.
+pub fn parse_reading(s: &str) -> Option<f64> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, "0"));
+    let decimal = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !decimal(int) || !decimal(frac) {
+        return None;
+    }
+    s.parse().ok().filter(|r: &f64| r.is_finite())
+}
+
+// Why parse_message turned a payload down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    // not a +++...*** frame of a known shape, or a field that does not parse
+    Format,
+    // a well-formed frame whose role never carries a reading here (ACK, Forward without
+    // aggregator mode, a role name that is not a word)
+    Role,
+    // +++RESET_REQUESTED***, usually the echo of the Pi's own reset broadcast
+    Reset,
+}
+
+// Ids end up in log records and (log.per_swarm) file names, so ids with control characters
+// (a newline would forge a record) or path separators are never accepted; commas already
+// split the fields. network.swarm_id_max_len and swarm_id_charset can narrow this further.
+pub fn valid_swarm_id(id: &str) -> bool {
+    !id.is_empty() && !id.chars().any(|c| c.is_control() || c == '/' || c == '\\')
+}
+
+// Accepts payloads:
+// 1) +++<role>,<swarm_id>,<reading>***    (Master, Slave or another role name)
+// 2) +++<role>,<swarm_id>,<reading>,<seq>***
+// 3) +++<swarm_id>,<reading>***    (optional fallback, a Master reading)
+// 4) +++Forward,<origin_pi>,<swarm_id>,<reading>***    (aggregator mode only, a Master reading)
+// 5) +++<role>,<swarm_id>,[<r1>,<r2>,...]***    (a batch of one or more readings)
+// 6) +++<role>,<swarm_id>,<r1>,<r2>,<r3>[,...]***    (a batch without brackets: three or more
+//    readings, since two fields after the id are a reading and its seq)
+pub fn parse_message(
+    payload: &str,
+    aggregator: bool,
+    markers: Markers,
+) -> Result<Packet, ParseError> {
+    let inner = markers.inner(payload).ok_or(ParseError::Format)?;
+
+    // ignore reset packets
+    if inner == RESET_REQUESTED {
+        return Err(ParseError::Reset);
+    }
+    let role = |name: &str| Role::from_name(name).ok_or(ParseError::Role);
+
+    // fields are trimmed; an empty or invalid swarm id (e.g. "Master,,512") is rejected, and
+    // so is a role name in the fallback shape (a truncated "Master,512" is not swarm "Master")
+    let parts: Vec<&str> = inner.split(',').map(|s| s.trim()).collect();
+    match parts.as_slice() {
+        [name, swarm_id, batch @ ..] if valid_swarm_id(swarm_id) && is_bracketed(batch) => {
+            Packet::new(role(name)?, swarm_id.to_string(), &unbracket(batch), None)
+        }
+        [swarm_id, reading]
+            if valid_swarm_id(swarm_id)
+                && ![MASTER_ROLE, SLAVE_ROLE, FORWARD_ROLE].contains(swarm_id) =>
+        {
+            Packet::new(Role::Master, swarm_id.to_string(), &[reading], None)
+        }
+        [name, swarm_id, reading] if valid_swarm_id(swarm_id) => {
+            Packet::new(role(name)?, swarm_id.to_string(), &[reading], None)
+        }
+        [FORWARD_ROLE, origin, swarm_id, reading] if valid_swarm_id(swarm_id) => {
+            if !aggregator {
+                return Err(ParseError::Role);
+            }
+            if !valid_swarm_id(origin) {
+                return Err(ParseError::Format);
+            }
+            let swarm_id = format!("{origin}/{swarm_id}");
+            Packet::new(Role::Master, swarm_id, &[reading], None)
+        }
+        [name, swarm_id, reading, seq] if valid_swarm_id(swarm_id) => {
+            Packet::new(role(name)?, swarm_id.to_string(), &[reading], Some(seq))
+        }
+        [name, swarm_id, readings @ ..] if readings.len() >= 3 && valid_swarm_id(swarm_id) => {
+            Packet::new(role(name)?, swarm_id.to_string(), readings, None)
+        }
+        _ => Err(ParseError::Format),
+    }
+}
+
+// The fields after the id of a "[<r1>,<r2>,...]" batch.
+fn is_bracketed(fields: &[&str]) -> bool {
+    fields.first().is_some_and(|f| f.starts_with('['))
+        && fields.last().is_some_and(|f| f.ends_with(']'))
+}
+
+// The readings of a bracketed batch, without the brackets ("[512]" is one reading).
+fn unbracket<'a>(fields: &[&'a str]) -> Vec<&'a str> {
+    let mut readings = fields.to_vec();
+    let last = readings.len() - 1;
+    readings[last] = readings[last][..readings[last].len() - 1].trim_end();
+    readings[0] = readings[0][1..].trim_start();
+    readings
+}
+
+// Binary frame, BINARY_FRAME_LEN bytes:
+// - type: BINARY_MASTER or BINARY_SLAVE
+// - swarm id: BINARY_ID_LEN bytes of UTF-8, padded with trailing NULs
+// - reading: i32, little-endian
+// The id is checked like a text frame's; there is no seq, batch or Forward shape.
+pub fn parse_binary(payload: &[u8]) -> Result<Packet, ParseError> {
+    let Ok([kind, id @ .., r0, r1, r2, r3]) = <[u8; BINARY_FRAME_LEN]>::try_from(payload) else {
+        return Err(ParseError::Format);
+    };
+    let role = match kind {
+        BINARY_MASTER => Role::Master,
+        BINARY_SLAVE => Role::Slave,
+        _ => return Err(ParseError::Role),
+    };
+    let len = id.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+    let swarm_id = std::str::from_utf8(&id[..len]).map_err(|_| ParseError::Format)?;
+    if !valid_swarm_id(swarm_id) {
+        return Err(ParseError::Format);
+    }
+    Ok(Packet {
+        role,
+        swarm_id: swarm_id.to_string(),
+        readings: vec![f64::from(i32::from_le_bytes([r0, r1, r2, r3]))],
+        seq: None,
+    })
+}
+
+fn is_command(payload: &[u8], command: &str, markers: Markers) -> bool {
+    payload
+        .strip_prefix(markers.start.as_bytes())
+        .and_then(|p| p.strip_suffix(markers.end.as_bytes()))
+        == Some(command.as_bytes())
+}
+
+// +++STATUS?*** (kept apart from parse_message so a query is never taken for a reading)
+pub fn is_status_query(payload: &[u8], markers: Markers) -> bool {
+    is_command(payload, STATUS_QUERY, markers)
+}
+
+// +++RESET_REQUESTED*** (parse_message ignores it; a remote reset is checked separately)
+pub fn is_reset_request(payload: &[u8], markers: Markers) -> bool {
+    is_command(payload, RESET_REQUESTED, markers)
+}
+
+// Inner part of a reading relayed to another Pi: Master,<id>,<reading>, or re-framed as
+// Forward,<origin>,<id>,<reading> for an aggregator.
+pub fn forward_inner(origin: Option<&str>, swarm_id: &str, reading: f64) -> String {
+    match origin {
+        Some(origin) => format!("{FORWARD_ROLE},{origin},{swarm_id},{reading}"),
+        None => format!("{MASTER_ROLE},{swarm_id},{reading}"),
+    }
+}
+
+// +++ACK,<swarm_id>,<reading>*** confirming a logged reading
+pub fn ack_frame(swarm_id: &str, reading: f64, markers: Markers) -> String {
+    markers.frame(&format!("{ACK_ROLE},{swarm_id},{reading}"))
+}
+
+// +++STATUS,<master_id>,<reading>,<led_index>,<blink_ms>***
+pub fn status_reply(
+    master_id: &str,
+    reading: f64,
+    led_index: usize,
+    blink_ms: u64,
+    markers: Markers,
+) -> String {
+    markers.frame(&format!(
+        "STATUS,{master_id},{reading},{led_index},{blink_ms}"
+    ))
+}
+
+pub fn find_marker(haystack: &[u8], marker: &str) -> Option<usize> {
+    haystack
+        .windows(marker.len())
+        .position(|w| w == marker.as_bytes())
+}
+
+// Pop the next complete +++...*** frame, dropping any bytes before its start marker.
+pub fn take_frame(pending: &mut Vec<u8>, markers: Markers) -> Option<Vec<u8>> {
+    let start = find_marker(pending, markers.start)?;
+    let body = start + markers.start.len();
+    let end = body + find_marker(&pending[body..], markers.end)? + markers.end.len();
+    let frame = pending[start..end].to_vec();
+    pending.drain(..end);
+    Some(frame)
+}
+
+// TCP streams may also carry bare newline-delimited lines (Master,<id>,<reading>\n): a line
+// without a start marker is popped and returned wrapped as a +++...*** frame, blank lines are
+// skipped, anything else is left to take_frame.
+pub fn take_line_or_frame(pending: &mut Vec<u8>, markers: Markers) -> Option<Vec<u8>> {
+    while let Some(newline) = pending.iter().position(|&b| b == b'\n') {
+        if find_marker(&pending[..newline], markers.start).is_some() {
+            break;
+        }
+        let line: Vec<u8> = pending.drain(..=newline).collect();
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim();
+        if !line.is_empty() {
+            return Some(markers.frame(line).into_bytes());
+        }
+    }
+    take_frame(pending, markers)
+}
+
+// Render a (possibly origin-namespaced) swarm key for the terminal:
+// "pi2/abc" -> "abc@pi2", local ids are shown unchanged.
+pub fn display_swarm_id(key: &str) -> String {
+    match key.split_once('/') {
+        Some((origin, swarm_id)) => format!("{swarm_id}@{origin}"),
+        None => key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(payload: &str) -> Option<Packet> {
+        parse_message(payload, false, DEFAULT_MARKERS).ok()
+    }
+
+    fn hit(swarm_id: &str, reading: f64) -> Option<Packet> {
+        Some(Packet {
+            role: Role::Master,
+            swarm_id: swarm_id.to_string(),
+            readings: vec![reading],
+            seq: None,
+        })
+    }
+
+    #[test]
+    fn master_frame() {
+        assert_eq!(parse("+++Master,abc,512***"), hit("abc", 512.0));
+        assert_eq!(parse("+++Master,abc,-3***"), hit("abc", -3.0));
+    }
+
+    #[test]
+    fn optional_sequence_number() {
+        let packet = parse("+++Master,abc,512,7***").unwrap();
+        assert_eq!((packet.readings, packet.seq), (vec![512.0], Some(7)));
+        assert_eq!(parse("+++Master,abc,512,x***"), None);
+        assert_eq!(parse("+++Master,abc,512,-1***"), None);
+        // in aggregator mode too: only Forward frames carry an origin
+        assert_eq!(
+            parse_message("+++Master,abc,512,7***", true, DEFAULT_MARKERS)
+                .unwrap()
+                .seq,
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn batched_readings() {
+        let packet = parse("+++Master,abc,510,512.5,515***").unwrap();
+        assert_eq!(packet.readings, [510.0, 512.5, 515.0]);
+        assert_eq!((packet.last_reading(), packet.seq), (515.0, None));
+        let packet = parse("+++Slave,abc,1,2,3,4,5***").unwrap();
+        assert_eq!((packet.role, packet.readings.len()), (Role::Slave, 5));
+        // one bad sample rejects the frame
+        assert_eq!(parse("+++Master,abc,1,x,3***"), None);
+        assert_eq!(parse("+++Master,abc,1,2,3,***"), None);
+        assert_eq!(parse("+++ACK,abc,1,2,3***"), None);
+        assert_eq!(
+            parse_message("+++Forward,pi2,abc,1,2***", true, DEFAULT_MARKERS).ok(),
+            None
+        );
+    }
+
+    #[test]
+    fn bracketed_batches_of_any_length() {
+        // two readings: not a reading and its seq
+        let packet = parse("+++Master,abc,[510,512]***").unwrap();
+        assert_eq!(packet.readings, [510.0, 512.0]);
+        assert_eq!(packet.seq, None);
+        let packet = parse("+++Master,abc,[510.5, 512.25]***").unwrap();
+        assert_eq!(packet.readings, [510.5, 512.25]);
+        let packet = parse("+++Slave,abc,[ 7 ]***").unwrap();
+        assert_eq!((packet.role, packet.readings), (Role::Slave, vec![7.0]));
+        let packet = parse("+++Master,abc,[1,2,3,4]***").unwrap();
+        assert_eq!(packet.readings.len(), 4);
+        // the unbracketed two-reading shape is still a reading and its seq
+        let packet = parse("+++Master,abc,510,512***").unwrap();
+        assert_eq!((packet.readings, packet.seq), (vec![510.0], Some(512)));
+        for bad in [
+            "+++Master,abc,[]***",
+            "+++Master,abc,[1,2***",
+            "+++Master,abc,1,2]***",
+            "+++Master,abc,[1,,2]***",
+            "+++Master,abc,[1,x]***",
+            "+++ACK,abc,[1,2]***",
+        ] {
+            assert_eq!(parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn two_field_fallback() {
+        assert_eq!(parse("+++abc,512***"), hit("abc", 512.0));
+    }
+
+    #[test]
+    fn other_roles_are_not_master() {
+        let role = |payload| parse(payload).map(|p| p.role);
+        assert_eq!(role("+++Slave,abc,512***"), Some(Role::Slave));
+        assert_eq!(role("+++Slave,abc,512,7***"), Some(Role::Slave));
+        let other = Some(Role::Other("master".to_string()));
+        assert_eq!(role("+++master,abc,512***"), other);
+        assert_eq!(role("+++ACK,abc,512***"), None);
+        assert_eq!(role("+++Forward,abc,512***"), None);
+        assert_eq!(role("+++12,abc,512***"), None);
+        assert_eq!(role("+++Slave,512***"), None);
+        assert_eq!(Role::Slave.to_string(), "Slave");
+        assert_eq!(Role::from_name("Master"), Some(Role::Master));
+    }
+
+    #[test]
+    fn forwarded_frames() {
+        // forwarded frames need aggregator mode
+        assert_eq!(parse("+++Forward,pi2,abc,512***"), None);
+        assert_eq!(
+            parse_message("+++Forward,pi2,abc,512***", true, DEFAULT_MARKERS).ok(),
+            hit("pi2/abc", 512.0)
+        );
+    }
+
+    #[test]
+    fn relayed_readings_parse_on_the_other_pi() {
+        let frame = |inner: String| format!("{RPI_START}{inner}{RPI_END}");
+        assert_eq!(forward_inner(None, "abc", 512.0), "Master,abc,512");
+        assert_eq!(
+            parse(&frame(forward_inner(None, "abc", 512.0))),
+            hit("abc", 512.0)
+        );
+        let forwarded = frame(forward_inner(Some("pi2"), "abc", 512.0));
+        assert_eq!(
+            parse_message(&forwarded, true, DEFAULT_MARKERS).ok(),
+            hit("pi2/abc", 512.0)
+        );
+    }
+
+    #[test]
+    fn tcp_streams_mix_lines_and_frames() {
+        let mut pending = b"Master,abc,512\r\n\n+++Master,def,7***\nMaster,gh".to_vec();
+        let mut take = || {
+            take_line_or_frame(&mut pending, DEFAULT_MARKERS).map(|f| String::from_utf8(f).unwrap())
+        };
+        assert_eq!(take().as_deref(), Some("+++Master,abc,512***"));
+        assert_eq!(take().as_deref(), Some("+++Master,def,7***"));
+        assert_eq!(take(), None);
+        assert_eq!(pending, b"Master,gh");
+    }
+
+    #[test]
+    fn rejects_missing_markers() {
+        assert_eq!(parse("Master,abc,512***"), None);
+        assert_eq!(parse("+++Master,abc,512"), None);
+        assert_eq!(parse("~~~abc,512---"), None);
+        assert_eq!(parse("+++Master,abc,512***\n"), None);
+        assert_eq!(parse("+++***"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn float_readings() {
+        assert_eq!(parse("+++Master,abc,512.75***"), hit("abc", 512.75));
+        assert_eq!(parse("+++Master,abc,-0.5,7***").unwrap().readings, [-0.5]);
+        // integers parse as before, and still print without a fraction
+        assert_eq!(
+            parse("+++Master,abc,99999999999***"),
+            hit("abc", 99999999999.0)
+        );
+        assert_eq!(
+            ack_frame("abc", 512.0, DEFAULT_MARKERS),
+            "+++ACK,abc,512***"
+        );
+        assert_eq!(
+            ack_frame("abc", 512.75, DEFAULT_MARKERS),
+            "+++ACK,abc,512.75***"
+        );
+    }
+
+    #[test]
+    fn rejects_non_numeric_readings() {
+        assert_eq!(parse("+++Master,abc,bright***"), None);
+        assert_eq!(parse("+++Master,abc,***"), None);
+        for reading in ["NaN", "-inf", "1e3", "0x10", "5.", ".5", "1.2.3", "--1"] {
+            assert_eq!(parse_reading(reading), None, "{reading}");
+        }
+        assert_eq!(parse_reading(&"9".repeat(400)), None);
+    }
+
+    #[test]
+    fn custom_markers() {
+        let markers = Markers {
+            start: "<<",
+            end: ">>",
+        };
+        let parse = |payload| parse_message(payload, false, markers).ok();
+        assert_eq!(parse("<<Master,a*b,512>>"), hit("a*b", 512.0));
+        assert_eq!(parse("+++Master,abc,512***"), None);
+        assert_eq!(parse("<<Master,abc,512>"), None);
+        assert!(is_reset_request(b"<<RESET_REQUESTED>>", markers));
+        assert_eq!(ack_frame("abc", 512.0, markers), "<<ACK,abc,512>>");
+        let mut pending = b"x<<Master,abc,1>><<Mas".to_vec();
+        assert_eq!(
+            take_frame(&mut pending, markers).as_deref(),
+            Some(&b"<<Master,abc,1>>"[..])
+        );
+        assert_eq!(take_frame(&mut pending, markers), None);
+        // markers that overlap in a short payload are not a frame
+        let markers = Markers {
+            start: "#",
+            end: "##",
+        };
+        assert_eq!(parse_message("##", false, markers), Err(ParseError::Format));
+    }
+
+    #[test]
+    fn binary_frames() {
+        let frame = |kind: u8, id: &[u8], reading: i32| {
+            let mut frame = vec![kind];
+            frame.extend_from_slice(id);
+            frame.resize(1 + BINARY_ID_LEN, 0);
+            frame.extend_from_slice(&reading.to_le_bytes());
+            frame
+        };
+        assert_eq!(
+            parse_binary(&frame(BINARY_MASTER, b"abc", 512)).ok(),
+            hit("abc", 512.0)
+        );
+        let packet = parse_binary(&frame(BINARY_SLAVE, b"kitchen1", -3)).unwrap();
+        assert_eq!(
+            (packet.role, packet.swarm_id),
+            (Role::Slave, "kitchen1".to_string())
+        );
+        assert_eq!(packet.readings, [-3.0]);
+
+        let why = |payload: &[u8]| parse_binary(payload).err();
+        assert_eq!(why(&frame(0x03, b"abc", 512)), Some(ParseError::Role));
+        assert_eq!(
+            why(&frame(BINARY_MASTER, b"", 512)),
+            Some(ParseError::Format)
+        );
+        assert_eq!(
+            why(&frame(BINARY_MASTER, b"a\0b", 512)),
+            Some(ParseError::Format)
+        );
+        assert_eq!(
+            why(&frame(BINARY_MASTER, b"a/b", 512)),
+            Some(ParseError::Format)
+        );
+        assert_eq!(
+            why(&frame(BINARY_MASTER, b"\xff", 512)),
+            Some(ParseError::Format)
+        );
+        // text frames are never mistaken for binary ones, nor the other way round
+        assert_eq!(why(b"+++Master,abc,512***"), Some(ParseError::Format));
+        // a 13-byte text frame starts with an unknown type
+        assert_eq!(why(b"+++abc,512***"), Some(ParseError::Role));
+        let binary = frame(BINARY_MASTER, b"abc", 512);
+        let text = String::from_utf8_lossy(&binary);
+        assert_eq!(parse_message(&text, false, DEFAULT_MARKERS).ok(), None);
+        assert_eq!(
+            why(&binary[..BINARY_FRAME_LEN - 1]),
+            Some(ParseError::Format)
+        );
+    }
+
+    #[test]
+    fn reset_packet_is_not_a_reading() {
+        assert_eq!(parse(&format!("{RPI_START}RESET_REQUESTED{RPI_END}")), None);
+        assert_eq!(
+            parse_message("+++RESET_REQUESTED***", true, DEFAULT_MARKERS).ok(),
+            None
+        );
+    }
+
+    #[test]
+    fn whitespace_is_trimmed_per_field() {
+        assert_eq!(parse("+++Master, abc , 512 ***"), hit("abc", 512.0));
+        assert_eq!(parse("+++ Master,abc,512***"), hit("abc", 512.0));
+        // inner spaces are part of the id
+        assert_eq!(parse("+++Master,a b,512***"), hit("a b", 512.0));
+    }
+
+    #[test]
+    fn rejects_empty_ids_and_bare_roles() {
+        assert_eq!(parse("+++Master,,512***"), None);
+        assert_eq!(parse("+++Master, ,512***"), None);
+        assert_eq!(parse("+++,512***"), None);
+        assert_eq!(parse("+++Master,512***"), None);
+        assert_eq!(
+            parse_message("+++Forward,pi2,,512***", true, DEFAULT_MARKERS).ok(),
+            None
+        );
+        assert_eq!(parse("+++Master,,1,2,3***"), None);
+    }
+
+    #[test]
+    fn rejects_ids_that_could_forge_records_or_paths() {
+        assert_eq!(parse("+++Master,a\nb,512***"), None);
+        assert_eq!(parse("+++Master,a\u{7f},512***"), None);
+        assert_eq!(parse("+++Master,../x,512***"), None);
+        assert_eq!(parse("+++Master,..\\x,512***"), None);
+        assert_eq!(parse("+++a/b,512***"), None);
+        assert_eq!(parse("+++Master,a/b,512,7***"), None);
+        assert_eq!(
+            parse_message("+++Forward,pi2,a/b,512***", true, DEFAULT_MARKERS).ok(),
+            None
+        );
+        assert_eq!(
+            parse_message("+++Forward,p\ti2,abc,512***", true, DEFAULT_MARKERS).ok(),
+            None
+        );
+        assert_eq!(parse("+++Master,a.b-c_d,512***"), hit("a.b-c_d", 512.0));
+    }
+
+    #[test]
+    fn rejections_say_why() {
+        let why = |payload| parse_message(payload, false, DEFAULT_MARKERS).err();
+        assert_eq!(why("+++Master,abc,512***"), None);
+        assert_eq!(why("+++Master,abc***"), Some(ParseError::Format));
+        assert_eq!(why("+++Master,abc,bright***"), Some(ParseError::Format));
+        assert_eq!(why("Master,abc,512"), Some(ParseError::Format));
+        assert_eq!(why("+++ACK,abc,512***"), Some(ParseError::Role));
+        assert_eq!(why("+++12,abc,512,7***"), Some(ParseError::Role));
+        assert_eq!(why("+++Forward,pi2,abc,512***"), Some(ParseError::Role));
+        assert_eq!(why("+++RESET_REQUESTED***"), Some(ParseError::Reset));
+    }
+
+    #[test]
+    fn ack_echoes_the_reading() {
+        assert_eq!(
+            ack_frame("abc", 512.0, DEFAULT_MARKERS),
+            "+++ACK,abc,512***"
+        );
+        assert_eq!(parse(&ack_frame("abc", 512.0, DEFAULT_MARKERS)), None);
+    }
+
+    #[test]
+    fn status_query_is_not_a_reading() {
+        assert!(is_status_query(b"+++STATUS?***", DEFAULT_MARKERS));
+        assert!(!is_status_query(b"+++STATUS***", DEFAULT_MARKERS));
+        assert!(!is_status_query(b"+++Master,abc,512***", DEFAULT_MARKERS));
+        assert_eq!(parse("+++STATUS?***"), None);
+        assert!(is_reset_request(b"+++RESET_REQUESTED***", DEFAULT_MARKERS));
+        assert!(!is_reset_request(
+            b"+++RESET_REQUESTED,abc***",
+            DEFAULT_MARKERS
+        ));
+        assert!(!is_reset_request(b"+++STATUS?***", DEFAULT_MARKERS));
+        assert_eq!(
+            status_reply("abc", 512.0, 1, 1058, DEFAULT_MARKERS),
+            "+++STATUS,abc,512,1,1058***"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// Saved sessions for --replay: sensor log records (text or CSV) and captured raw frames.
+
+use crate::protocol::{parse_reading, Markers, Role};
+use chrono::DateTime;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayItem {
+    // A logged reading: it already passed validation when it was received.
+    Reading {
+        role: Role,
+        swarm_id: String,
+        reading: f64,
+        sender: Option<IpAddr>,
+    },
+    // A captured +++...*** frame, validated again like a received one.
+    Frame(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayLine {
+    // Original receive time (log.timestamp rfc3339 or epoch_ms), used for pacing.
+    pub ts_ms: Option<i64>,
+    pub item: ReplayItem,
+}
+
+fn parse_timestamp(s: &str) -> Option<i64> {
+    s.parse().ok().or_else(|| {
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.timestamp_millis())
+    })
+}
+
+// <ts>[ #seq] Swarm ID <id>: <reading>[ sender=<ip>][ role=<role>][ out_of_range][ raw=...]
+fn parse_text_record(line: &str) -> Option<ReplayLine> {
+    let (head, rest) = line.split_once(" Swarm ID ")?;
+    let ts_ms = head.split_whitespace().next().and_then(parse_timestamp);
+    let (swarm_id, rest) = rest.split_once(": ")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let (reading, fields) = fields.split_first()?;
+    let field = |name: &str| fields.iter().find_map(|f| f.strip_prefix(name));
+    let sender = field("sender=").and_then(|ip| ip.parse().ok());
+    // records without a role are Master readings
+    let role = field("role=").map_or(Some(Role::Master), Role::from_name)?;
+    Some(ReplayLine {
+        ts_ms,
+        item: ReplayItem::Reading {
+            role,
+            swarm_id: swarm_id.to_string(),
+            reading: parse_reading(reading)?,
+            sender,
+        },
+    })
+}
+
+// [<ts> ]+++...***
+fn parse_frame(line: &str, markers: Markers) -> Option<ReplayLine> {
+    let start = line.find(markers.start)?;
+    let end = line.rfind(markers.end)? + markers.end.len();
+    let frame = line.get(start..end)?;
+    let ts_ms = parse_timestamp(line[..start].trim());
+    Some(ReplayLine {
+        ts_ms,
+        item: ReplayItem::Frame(frame.to_string()),
+    })
+}
+
+// <ts>,<swarm_id>,<reading>,<sender_ip>[,<blink_ms>,<led_index>] (the header fails to parse
+// and is skipped)
+fn parse_csv_record(line: &str) -> Option<ReplayLine> {
+    let mut fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() == 6 {
+        // log.blink_fields columns, not needed to replay the reading
+        fields.truncate(4);
+    }
+    let [ts, swarm_id, reading, sender] = fields[..] else {
+        return None;
+    };
+    Some(ReplayLine {
+        ts_ms: Some(parse_timestamp(ts)?),
+        item: ReplayItem::Reading {
+            role: Role::Master,
+            swarm_id: swarm_id.to_string(),
+            reading: parse_reading(reading)?,
+            sender: sender.parse().ok(),
+        },
+    })
+}
+
+// One line of a saved session, or None for blank and unrecognized lines. Captured frames are
+// recognized by the configured markers.
+pub fn parse_line(line: &str, markers: Markers) -> Option<ReplayLine> {
+    let line = line.trim();
+    parse_text_record(line)
+        .or_else(|| parse_frame(line, markers))
+        .or_else(|| parse_csv_record(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::DEFAULT_MARKERS;
+
+    fn parse(line: &str) -> Option<ReplayLine> {
+        parse_line(line, DEFAULT_MARKERS)
+    }
+
+    fn reading(swarm_id: &str, reading: f64, sender: Option<&str>) -> ReplayItem {
+        ReplayItem::Reading {
+            role: Role::Master,
+            swarm_id: swarm_id.to_string(),
+            reading,
+            sender: sender.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn text_records() {
+        let line = parse("2024-05-01T12:00:00.250Z #7 Swarm ID abc: 512 sender=10.0.0.5 raw=\"x\"");
+        assert_eq!(
+            line,
+            Some(ReplayLine {
+                ts_ms: Some(1_714_564_800_250),
+                item: reading("abc", 512.0, Some("10.0.0.5")),
+            })
+        );
+        let line = parse("1714564800250 Swarm ID pi2/abc: 9").unwrap();
+        assert_eq!(line.ts_ms, Some(1_714_564_800_250));
+        assert_eq!(line.item, reading("pi2/abc", 9.0, None));
+        let line = parse("1714564800250 Swarm ID abc: 9 sender=10.0.0.5 role=Slave").unwrap();
+        let ReplayItem::Reading { role, .. } = line.item else {
+            panic!("not a reading: {line:?}");
+        };
+        assert_eq!(role, Role::Slave);
+    }
+
+    #[test]
+    fn csv_records() {
+        assert_eq!(parse("timestamp,swarm_id,reading,sender_ip"), None);
+        let line = parse("1714564800250,abc,512,10.0.0.5").unwrap();
+        assert_eq!(line.item, reading("abc", 512.0, Some("10.0.0.5")));
+        let line = parse("1714564800250,abc,512.75,10.0.0.5").unwrap();
+        assert_eq!(line.item, reading("abc", 512.75, Some("10.0.0.5")));
+        // log.blink_fields columns
+        let line = parse("1714564800250,abc,512,10.0.0.5,1058,").unwrap();
+        assert_eq!(line.item, reading("abc", 512.0, Some("10.0.0.5")));
+    }
+
+    #[test]
+    fn raw_frames() {
+        let line = parse("+++Master,abc,512***").unwrap();
+        assert_eq!(line.ts_ms, None);
+        assert_eq!(
+            line.item,
+            ReplayItem::Frame("+++Master,abc,512***".to_string())
+        );
+        let line = parse("1714564800250 +++Master,abc,512***").unwrap();
+        assert_eq!(line.ts_ms, Some(1_714_564_800_250));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("garbage"), None);
+    }
+}
@@ -0,0 +1,107 @@
+// Where the receive loop gets its datagrams and sends its replies (ACKs, STATUS): the UDP
+// socket, or a MemorySource standing in for the network in tests.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+pub trait PacketSource {
+    // Like UdpSocket::recv_from: the datagram is cut to `buf`, and a WouldBlock or TimedOut
+    // error means nothing arrived within the read timeout.
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    fn send_to(&mut self, buf: &[u8], addr: SocketAddr) -> io::Result<usize>;
+}
+
+impl PacketSource for UdpSocket {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn send_to(&mut self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, addr)
+    }
+}
+
+// --dry-run: receives from the wrapped source, and drops whatever is sent as if it was sent.
+pub struct ReceiveOnly<S>(pub S);
+
+impl<S: PacketSource> PacketSource for ReceiveOnly<S> {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.0.recv_from(buf)
+    }
+
+    fn send_to(&mut self, buf: &[u8], _addr: SocketAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+// Datagrams queued with push() are received in order, then every read times out right away;
+// what is sent is kept in `sent`.
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    pub incoming: VecDeque<(Vec<u8>, SocketAddr)>,
+    pub sent: Vec<(Vec<u8>, SocketAddr)>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, payload: impl Into<Vec<u8>>, from: SocketAddr) {
+        self.incoming.push_back((payload.into(), from));
+    }
+}
+
+impl PacketSource for MemorySource {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let Some((payload, from)) = self.incoming.pop_front() else {
+            return Err(io::ErrorKind::TimedOut.into());
+        };
+        let n = payload.len().min(buf.len());
+        buf[..n].copy_from_slice(&payload[..n]);
+        Ok((n, from))
+    }
+
+    fn send_to(&mut self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.sent.push((buf.to_vec(), addr));
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_source_behaves_like_a_socket() {
+        let peer: SocketAddr = "10.0.0.5:4210".parse().unwrap();
+        let mut source = MemorySource::new();
+        source.push("+++Master,abc,512***", peer);
+        source.push(vec![b'x'; 8], peer);
+
+        let mut buf = [0u8; 32];
+        let (n, from) = source.recv_from(&mut buf).unwrap();
+        assert_eq!((&buf[..n], from), (&b"+++Master,abc,512***"[..], peer));
+        // a datagram larger than the buffer is cut, like a UDP read
+        let (n, _) = source.recv_from(&mut buf[..4]).unwrap();
+        assert_eq!(n, 4);
+        let err = source.recv_from(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        source.send_to(b"+++ACK,abc,512***", peer).unwrap();
+        assert_eq!(source.sent, [(b"+++ACK,abc,512***".to_vec(), peer)]);
+    }
+
+    #[test]
+    fn receive_only_sends_nothing() {
+        let peer: SocketAddr = "10.0.0.5:4210".parse().unwrap();
+        let mut inner = MemorySource::new();
+        inner.push("+++Master,abc,512***", peer);
+        let mut source = ReceiveOnly(inner);
+        let mut buf = [0u8; 32];
+        assert_eq!(source.recv_from(&mut buf).unwrap(), (20, peer));
+        assert_eq!(source.send_to(b"+++ACK,abc,512***", peer).unwrap(), 17);
+        assert!(source.0.sent.is_empty());
+    }
+}
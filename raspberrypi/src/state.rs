@@ -0,0 +1,1079 @@
+use crate::blink::{blink_interval, BlinkMap};
+use crate::config::{Config, RgbColor};
+use crate::event::{AlarmBound, RosterEntry};
+use crate::stats::{MovingAverage, SwarmStats};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// The STATUS line's packets per second cover this trailing window.
+const PPS_WINDOW: Duration = Duration::from_secs(1);
+
+// About a year: stands in for deadlines beyond what Instant can hold.
+const FAR_FUTURE_SECS: u64 = 365 * 24 * 3600;
+
+// Swarm color mode: colors for swarms without a gpio.swarm_colors entry, in the order given out.
+const AUTO_COLORS: [RgbColor; 8] = [
+    RgbColor([0, 0, 255]),     // blue
+    RgbColor([0, 255, 0]),     // green
+    RgbColor([255, 0, 0]),     // red
+    RgbColor([255, 255, 0]),   // yellow
+    RgbColor([0, 255, 255]),   // cyan
+    RgbColor([255, 0, 255]),   // magenta
+    RgbColor([255, 128, 0]),   // orange
+    RgbColor([255, 255, 255]), // white
+];
+
+// Next deadline for a periodic task: now + period + random 0..=jitter ms
+pub fn jittered_deadline(period_ms: u64, jitter_ms: u64) -> Instant {
+    let jitter = if jitter_ms == 0 {
+        0
+    } else {
+        RandomState::new().build_hasher().finish() % (jitter_ms + 1)
+    };
+    let now = Instant::now();
+    // a period too long to represent is as good as never
+    now.checked_add(Duration::from_millis(period_ms.saturating_add(jitter)))
+        .unwrap_or(now + Duration::from_secs(FAR_FUTURE_SECS))
+}
+
+// A swarm trying to take over from the current Master (hysteresis)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MasterCandidate {
+    pub id: String,
+    pub packets: u32,
+    pub since: Instant,
+}
+
+// A Master swarm blinking its own LED alongside the current Master (multi-master mode)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlinkingMaster {
+    pub swarm_id: String,
+    pub reading: f64,
+    pub interval: Duration,
+    pub seen: Instant,
+}
+
+// Frames dropped before they became a reading: not UTF-8, not a known frame shape, or a role
+// that never carries a reading (see protocol::ParseError)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeDrops {
+    pub bad_utf8: u64,
+    pub bad_format: u64,
+    pub rejected_role: u64,
+}
+
+// Latest sequence number from a swarm and the packets found missing so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqTrack {
+    pub last: u64,
+    pub lost: u64,
+}
+
+// ===== State =====
+#[derive(Debug)]
+pub struct SharedState {
+    pub swarm_to_led: HashMap<String, usize>,
+    pub next_led_index: usize,
+    // swarm_to_led has changed since it was last saved to gpio.led_map_file
+    pub led_map_changed: bool,
+    // Swarm color mode: colors given to swarms without a gpio.swarm_colors entry (kept across
+    // resets, so a swarm keeps its color for the whole session)
+    pub auto_colors: HashMap<String, RgbColor>,
+
+    // What the blink timer should drive: the current Master's LED and interval
+    // (only one should blink: the current Master). The timer thread owns the toggle state.
+    pub active_led: Option<usize>,
+    pub blink_interval: Option<Duration>,
+    // Multi-master mode: every recent Master swarm by its LED index (the latest swarm wins a
+    // shared LED); the blink timer drives these besides active_led.
+    pub blinking_masters: HashMap<usize, BlinkingMaster>,
+
+    // For terminal output
+    pub last_master_id: Option<String>,
+    pub last_reading: Option<f64>,
+    // Smoothed current-Master readings that drive the blink interval (blink.smoothing_window)
+    pub smoothed_reading: MovingAverage,
+    // Source address of the current Master's packets
+    pub last_master_sender: Option<IpAddr>,
+    // Arrival of the current Master's latest packet, for stale-master detection
+    pub last_master_seen: Option<Instant>,
+    // When the current Master took over (its dwell so far isn't in its master_time yet)
+    pub master_since: Option<Instant>,
+    pub master_candidate: Option<MasterCandidate>,
+    pub next_status_print: Instant,
+    // [alarm] bound the current Master's reading is at, and whether the blink timer still
+    // has to light the white LED for it
+    pub alarm: Option<AlarmBound>,
+    pub alarm_flash: bool,
+
+    // Latest accepted packet per swarm id, whatever its role, for the roster line
+    pub last_seen: HashMap<String, Instant>,
+    pub next_roster_print: Instant,
+
+    // Recent master switches (and attempts while holding) for flap detection
+    pub master_change_times: VecDeque<Instant>,
+    pub stabilize_until: Option<Instant>,
+
+    // Inventory: expected ids heard since the last report, unknown ids and their packet count
+    pub reported_swarms: HashSet<String>,
+    pub unknown_swarms: HashSet<String>,
+    pub unknown_swarm_packets: u64,
+    // Packets dropped because their id is not in network.allowed_swarm_ids
+    pub rejected_unknown_swarm: u64,
+    pub next_inventory_report: Instant,
+
+    // count/min/max/mean per swarm id since start (or the last reset)
+    pub swarm_stats: HashMap<String, SwarmStats>,
+    // Ring buffer of the latest (ts_ms, reading) per swarm id, history.capacity long
+    pub recent_readings: HashMap<String, VecDeque<(u128, f64)>>,
+    // Latest reading per swarm id and how many times in a row it has arrived (stuck sensors)
+    pub repeated_readings: HashMap<String, (f64, u32)>,
+    // Sequence tracking for swarms whose packets carry a seq
+    pub swarm_seq: HashMap<String, SeqTrack>,
+
+    // Frames dropped while decoding, by cause (never reset), and the counts of the last
+    // diagnostics line
+    pub decode_drops: DecodeDrops,
+    pub reported_decode_drops: DecodeDrops,
+    pub next_diagnostics_print: Instant,
+    // Next heartbeat line (status.heartbeat_ms; not moved by resets)
+    pub next_heartbeat: Instant,
+
+    // Prometheus counters: never reset, so they only ever grow
+    pub packets_received: u64,
+    pub packets_rejected: u64,
+    pub master_changes: u64,
+    // Accepted readings not logged because the log writer's queue was full
+    pub log_records_dropped: u64,
+    // LED writes the GPIO line rejected
+    pub gpio_write_errors: u64,
+    // Arrival of every frame received within the last PPS_WINDOW, for the STATUS pps
+    pub recent_packets: VecDeque<Instant>,
+
+    // After a button reset, received packets are dropped until then (gpio.reset_cooldown_ms)
+    pub reset_cooldown_until: Option<Instant>,
+
+    // Per-session log record counter (not a protocol sequence number)
+    pub log_record_seq: u64,
+    // The log queue was full for the latest record (warned once per stretch of drops)
+    pub log_queue_full: bool,
+
+    // Current reading -> blink interval mapping (kept across resets)
+    pub blink_map: BlinkMap,
+
+    pub cfg: Arc<Config>,
+
+    // Program start for timestamps
+    pub start: Instant,
+}
+
+impl SharedState {
+    pub fn new(cfg: Arc<Config>, blink_map: BlinkMap) -> Self {
+        Self {
+            swarm_to_led: HashMap::new(),
+            auto_colors: HashMap::new(),
+            next_led_index: 0,
+            led_map_changed: false,
+            active_led: None,
+            blink_interval: None,
+            blinking_masters: HashMap::new(),
+            last_master_id: None,
+            last_reading: None,
+            smoothed_reading: MovingAverage::new(cfg.blink.smoothing_window),
+            last_master_sender: None,
+            last_master_seen: None,
+            master_since: None,
+            master_candidate: None,
+            next_status_print: jittered_deadline(cfg.status.print_ms, cfg.status.jitter_ms),
+            alarm: None,
+            alarm_flash: false,
+            last_seen: HashMap::new(),
+            next_roster_print: jittered_deadline(cfg.status.roster_ms, cfg.status.jitter_ms),
+            master_change_times: VecDeque::new(),
+            stabilize_until: None,
+            reported_swarms: HashSet::new(),
+            unknown_swarms: HashSet::new(),
+            unknown_swarm_packets: 0,
+            rejected_unknown_swarm: 0,
+            next_inventory_report: jittered_deadline(
+                cfg.inventory.report_ms,
+                cfg.inventory.jitter_ms,
+            ),
+            swarm_stats: HashMap::new(),
+            recent_readings: HashMap::new(),
+            repeated_readings: HashMap::new(),
+            swarm_seq: HashMap::new(),
+            decode_drops: DecodeDrops::default(),
+            reported_decode_drops: DecodeDrops::default(),
+            next_diagnostics_print: jittered_deadline(
+                cfg.status.diagnostics_ms,
+                cfg.status.jitter_ms,
+            ),
+            next_heartbeat: jittered_deadline(cfg.status.heartbeat_ms, cfg.status.jitter_ms),
+            packets_received: 0,
+            packets_rejected: 0,
+            master_changes: 0,
+            log_records_dropped: 0,
+            gpio_write_errors: 0,
+            recent_packets: VecDeque::new(),
+            reset_cooldown_until: None,
+            log_record_seq: 0,
+            log_queue_full: false,
+            blink_map,
+            cfg,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn ts_ms(&self) -> u128 {
+        self.start.elapsed().as_millis()
+    }
+
+    pub fn led_label(idx: usize) -> String {
+        format!("LED{idx}")
+    }
+
+    // The swarm's color in swarm color mode: its gpio.swarm_colors entry, or else the first of
+    // AUTO_COLORS no other swarm has (reused in turn once all are taken).
+    pub fn swarm_color(&mut self, swarm_id: &str) -> RgbColor {
+        let pinned = &self.cfg.gpio.swarm_colors;
+        if let Some(color) = pinned.get(swarm_id).or(self.auto_colors.get(swarm_id)) {
+            return *color;
+        }
+        let taken: HashSet<RgbColor> = pinned
+            .values()
+            .chain(self.auto_colors.values())
+            .copied()
+            .collect();
+        let color = AUTO_COLORS
+            .into_iter()
+            .find(|color| !taken.contains(color))
+            .unwrap_or(AUTO_COLORS[self.auto_colors.len() % AUTO_COLORS.len()]);
+        self.auto_colors.insert(swarm_id.to_string(), color);
+        color
+    }
+
+    pub fn assign_led_index(&mut self, swarm_id: &str) -> usize {
+        // pinned in gpio.led_assignments: always that LED, whatever was assigned before
+        if let Some(&idx) = self.cfg.gpio.led_assignments.get(swarm_id) {
+            if self.swarm_to_led.insert(swarm_id.to_string(), idx) != Some(idx) {
+                self.led_map_changed = true;
+            }
+            return idx;
+        }
+        if let Some(&idx) = self.swarm_to_led.get(swarm_id) {
+            return idx;
+        }
+        // one LED per configured RGB pin; swarms beyond that share LEDs round-robin, skipping
+        // the pinned ones (unless every LED is pinned)
+        let leds = self.cfg.gpio.rgb_led_pins.len();
+        let idx = (0..leds)
+            .map(|i| (self.next_led_index + i) % leds)
+            .find(|&i| !self.is_pinned(i))
+            .unwrap_or(self.next_led_index);
+        self.swarm_to_led.insert(swarm_id.to_string(), idx);
+        self.led_map_changed = true;
+        self.next_led_index = (idx + 1) % leds;
+        idx
+    }
+
+    // Whether the LED index is pinned to a swarm in gpio.led_assignments.
+    fn is_pinned(&self, idx: usize) -> bool {
+        self.cfg.gpio.led_assignments.values().any(|&i| i == idx)
+    }
+
+    // Assignments saved by an earlier run; indices beyond the configured pins, or pinned to
+    // another swarm since, are dropped.
+    pub fn restore_led_map(&mut self, map: HashMap<String, usize>) {
+        let leds = self.cfg.gpio.rgb_led_pins.len();
+        let pinned = &self.cfg.gpio.led_assignments;
+        self.swarm_to_led = map
+            .into_iter()
+            .filter(|(id, idx)| *idx < leds && (pinned.contains_key(id) || !self.is_pinned(*idx)))
+            .collect();
+        // new swarms continue round-robin after the highest restored index
+        if let Some(max) = self.swarm_to_led.values().max() {
+            self.next_led_index = (max + 1) % leds;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        // a persisted LED map survives resets; otherwise LEDs are assigned afresh
+        if self.cfg.gpio.led_map_file.is_none() {
+            self.swarm_to_led.clear();
+            self.next_led_index = 0;
+        }
+        self.active_led = None;
+        self.blink_interval = None;
+        self.blinking_masters.clear();
+        self.last_master_id = None;
+        self.last_reading = None;
+        self.smoothed_reading.clear();
+        self.last_master_sender = None;
+        self.last_master_seen = None;
+        self.master_since = None;
+        self.master_candidate = None;
+        self.next_status_print =
+            jittered_deadline(self.cfg.status.print_ms, self.cfg.status.jitter_ms);
+        self.alarm = None;
+        self.alarm_flash = false;
+        self.last_seen.clear();
+        self.master_change_times.clear();
+        self.stabilize_until = None;
+        self.swarm_stats.clear();
+        self.recent_readings.clear();
+        self.repeated_readings.clear();
+        self.swarm_seq.clear();
+        self.reported_swarms.clear();
+        self.unknown_swarms.clear();
+        self.unknown_swarm_packets = 0;
+        self.rejected_unknown_swarm = 0;
+        self.next_inventory_report =
+            jittered_deadline(self.cfg.inventory.report_ms, self.cfg.inventory.jitter_ms);
+    }
+
+    // Whether a packet from `swarm_id` may drive the Master. A different swarm must send
+    // master.confirm_packets consecutive packets over at least master.confirm_ms first.
+    pub fn confirm_master(&mut self, swarm_id: &str, now: Instant) -> bool {
+        let current = self.last_master_id.as_deref();
+        if current.is_none() || current == Some(swarm_id) {
+            self.master_candidate = None;
+            return true;
+        }
+        let candidate = match self.master_candidate.take() {
+            Some(mut c) if c.id == swarm_id => {
+                c.packets += 1;
+                c
+            }
+            _ => MasterCandidate {
+                id: swarm_id.to_string(),
+                packets: 1,
+                since: now,
+            },
+        };
+        let master = &self.cfg.master;
+        if candidate.packets >= master.confirm_packets
+            && now.saturating_duration_since(candidate.since)
+                >= Duration::from_millis(master.confirm_ms)
+        {
+            return true;
+        }
+        self.master_candidate = Some(candidate);
+        false
+    }
+
+    // A frame arrived (UDP or TCP, accepted or not).
+    pub fn note_received(&mut self, now: Instant) {
+        self.packets_received += 1;
+        self.recent_packets.push_back(now);
+        while self
+            .recent_packets
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) >= PPS_WINDOW)
+        {
+            self.recent_packets.pop_front();
+        }
+    }
+
+    // Frames received in the PPS_WINDOW before `now`.
+    pub fn packets_per_second(&self, now: Instant) -> usize {
+        self.recent_packets
+            .iter()
+            .filter(|t| now.saturating_duration_since(**t) < PPS_WINDOW)
+            .count()
+    }
+
+    // Whether a STATUS line is due (and schedule the next one); status.print_ms = 0 disables them.
+    pub fn status_due(&mut self, now: Instant) -> bool {
+        let status = &self.cfg.status;
+        if status.print_ms == 0 || now < self.next_status_print {
+            return false;
+        }
+        self.next_status_print = jittered_deadline(status.print_ms, status.jitter_ms);
+        true
+    }
+
+    // Every swarm heard from since start (or the last reset), by id, once status.roster_ms has
+    // passed; None while not due, disabled or before the first packet.
+    pub fn take_roster(&mut self, now: Instant) -> Option<Vec<RosterEntry>> {
+        let status = &self.cfg.status;
+        if status.roster_ms == 0 || now < self.next_roster_print || self.last_seen.is_empty() {
+            return None;
+        }
+        self.next_roster_print = jittered_deadline(status.roster_ms, status.jitter_ms);
+        let mut roster: Vec<RosterEntry> = self
+            .last_seen
+            .iter()
+            .map(|(id, seen)| RosterEntry {
+                swarm_id: id.clone(),
+                last_seen_secs: now.saturating_duration_since(*seen).as_secs(),
+                led_index: self.swarm_to_led.get(id).copied(),
+            })
+            .collect();
+        roster.sort_by(|a, b| a.swarm_id.cmp(&b.swarm_id));
+        Some(roster)
+    }
+
+    // The decode drop counts once status.diagnostics_ms has passed; None while not due,
+    // disabled or unchanged since the last diagnostics line.
+    pub fn take_diagnostics(&mut self, now: Instant) -> Option<DecodeDrops> {
+        let status = &self.cfg.status;
+        if status.diagnostics_ms == 0 || now < self.next_diagnostics_print {
+            return None;
+        }
+        self.next_diagnostics_print = jittered_deadline(status.diagnostics_ms, status.jitter_ms);
+        if self.decode_drops == self.reported_decode_drops {
+            return None;
+        }
+        self.reported_decode_drops = self.decode_drops;
+        Some(self.decode_drops)
+    }
+
+    // Whether a heartbeat line is due (and schedule the next one); status.heartbeat_ms = 0
+    // disables them.
+    pub fn heartbeat_due(&mut self, now: Instant) -> bool {
+        let status = &self.cfg.status;
+        if status.heartbeat_ms == 0 || now < self.next_heartbeat {
+            return false;
+        }
+        self.next_heartbeat = jittered_deadline(status.heartbeat_ms, status.jitter_ms);
+        true
+    }
+
+    pub fn in_reset_cooldown(&self, now: Instant) -> bool {
+        self.reset_cooldown_until.is_some_and(|until| now < until)
+    }
+
+    // Forget a Master that has been silent for longer than master.timeout_ms; returns its id.
+    pub fn take_stale_master(&mut self, now: Instant) -> Option<String> {
+        let timeout = Duration::from_millis(self.cfg.master.timeout_ms);
+        let seen = self.last_master_seen?;
+        if timeout.is_zero() || now.saturating_duration_since(seen) < timeout {
+            return None;
+        }
+        self.active_led = None;
+        self.blink_interval = None;
+        self.last_reading = None;
+        self.smoothed_reading.clear();
+        self.alarm = None;
+        self.last_master_sender = None;
+        self.last_master_seen = None;
+        self.end_master_dwell(now);
+        self.last_master_id.take()
+    }
+
+    // The current Master lost the role (replaced or timed out): its dwell is added to its
+    // master_time and returned.
+    pub fn end_master_dwell(&mut self, now: Instant) -> Option<Duration> {
+        let dwell = now.saturating_duration_since(self.master_since.take()?);
+        let id = self.last_master_id.as_ref()?;
+        if let Some(stats) = self.swarm_stats.get_mut(id) {
+            stats.master_time += dwell;
+        }
+        Some(dwell)
+    }
+
+    // swarm_stats with the current Master's dwell so far counted in, for the summary.
+    pub fn swarm_stats_at(&self, now: Instant) -> HashMap<String, SwarmStats> {
+        let mut stats = self.swarm_stats.clone();
+        if let (Some(id), Some(since)) = (&self.last_master_id, self.master_since) {
+            if let Some(stats) = stats.get_mut(id) {
+                stats.master_time += now.saturating_duration_since(since);
+            }
+        }
+        stats
+    }
+
+    // Multi-master mode: a Master reading keeps its swarm's own LED blinking at the rate of
+    // that reading (not while its sensor is stuck with reading.stuck_dark).
+    pub fn note_blinking_master(&mut self, swarm_id: &str, reading: f64, now: Instant) {
+        if !self.cfg.multi_master() {
+            return;
+        }
+        let idx = self.assign_led_index(swarm_id);
+        if self.cfg.reading.stuck_dark && self.is_stuck(swarm_id) {
+            self.blinking_masters.remove(&idx);
+            return;
+        }
+        let interval = blink_interval(&self.blink_map, reading);
+        let master = BlinkingMaster {
+            swarm_id: swarm_id.to_string(),
+            reading,
+            interval,
+            seen: now,
+        };
+        self.blinking_masters.insert(idx, master);
+    }
+
+    // Multi-master mode: (LED index, reading, interval) of the Masters blinking besides the
+    // current one; those silent for longer than master.timeout_ms are dropped first.
+    pub fn other_masters(&mut self, now: Instant) -> Vec<(usize, f64, Duration)> {
+        let timeout = Duration::from_millis(self.cfg.master.timeout_ms);
+        if !timeout.is_zero() {
+            self.blinking_masters
+                .retain(|_, m| now.saturating_duration_since(m.seen) < timeout);
+        }
+        let mut others: Vec<_> = self
+            .blinking_masters
+            .iter()
+            .filter(|(idx, _)| Some(**idx) != self.active_led)
+            .map(|(idx, m)| (*idx, m.reading, m.interval))
+            .collect();
+        others.sort_by_key(|(idx, ..)| *idx);
+        others
+    }
+
+    // [alarm] check of a Master reading; returns (bound, threshold) when an alarm starts.
+    // An alarm lasts until the reading is back inside its bound by alarm.hysteresis.
+    pub fn check_alarm(&mut self, reading: f64) -> Option<(AlarmBound, f64)> {
+        let cfg = &self.cfg.alarm;
+        let margin = f64::from(cfg.hysteresis);
+        let crossed = match (cfg.low, cfg.high) {
+            (_, Some(high)) if reading >= high => Some((AlarmBound::High, high)),
+            (Some(low), _) if reading <= low => Some((AlarmBound::Low, low)),
+            _ => None,
+        };
+        let held = self.alarm.filter(|bound| match (bound, cfg.low, cfg.high) {
+            (AlarmBound::Low, Some(low), _) => reading < low + margin,
+            (AlarmBound::High, _, Some(high)) => reading > high - margin,
+            _ => false,
+        });
+        let alarm = crossed.map(|(bound, _)| bound).or(held);
+        let started = crossed.filter(|&(bound, _)| self.alarm != Some(bound));
+        self.alarm = alarm;
+        self.alarm_flash |= started.is_some();
+        started
+    }
+
+    // Returns Some(first_sighting) when the id is not in the expected inventory.
+    pub fn note_swarm_reported(&mut self, swarm_id: &str) -> Option<bool> {
+        let expected = &self.cfg.inventory.expected_swarm_ids;
+        if expected.is_empty() {
+            return None;
+        }
+        if expected.iter().any(|id| id == swarm_id) {
+            self.reported_swarms.insert(swarm_id.to_string());
+            return None;
+        }
+        self.unknown_swarm_packets += 1;
+        Some(self.unknown_swarms.insert(swarm_id.to_string()))
+    }
+
+    // Expected ids not heard since the previous report, once the report is due.
+    pub fn take_missing_swarms(&mut self) -> Option<Vec<String>> {
+        let inventory = &self.cfg.inventory;
+        if inventory.expected_swarm_ids.is_empty() || Instant::now() < self.next_inventory_report {
+            return None;
+        }
+        self.next_inventory_report = jittered_deadline(inventory.report_ms, inventory.jitter_ms);
+        let missing = inventory
+            .expected_swarm_ids
+            .iter()
+            .filter(|id| !self.reported_swarms.contains(*id))
+            .cloned()
+            .collect();
+        self.reported_swarms.clear();
+        Some(missing)
+    }
+
+    pub fn record_reading(&mut self, swarm_id: &str, reading: f64) {
+        self.last_seen.insert(swarm_id.to_string(), Instant::now());
+        match self.swarm_stats.get_mut(swarm_id) {
+            Some(stats) => stats.record(reading),
+            None => {
+                self.swarm_stats
+                    .insert(swarm_id.to_string(), SwarmStats::new(reading));
+            }
+        }
+
+        let capacity = self.cfg.history.capacity;
+        if capacity == 0 {
+            return;
+        }
+        let ts_ms = self.ts_ms();
+        let recent = self
+            .recent_readings
+            .entry(swarm_id.to_string())
+            .or_insert_with(|| VecDeque::with_capacity(capacity));
+        if recent.len() == capacity {
+            recent.pop_front();
+        }
+        recent.push_back((ts_ms, reading));
+    }
+
+    // Count a reading against the swarm's run of identical readings; returns the run length
+    // once, when it first exceeds reading.stuck_limit.
+    pub fn note_repeat(&mut self, swarm_id: &str, reading: f64) -> Option<u32> {
+        let run = self
+            .repeated_readings
+            .entry(swarm_id.to_string())
+            .or_insert((reading, 0));
+        if run.0 == reading {
+            run.1 = run.1.saturating_add(1);
+        } else {
+            *run = (reading, 1);
+        }
+        let limit = self.cfg.reading.stuck_limit;
+        (limit > 0 && run.1 == limit.saturating_add(1)).then_some(run.1)
+    }
+
+    // Whether the swarm's latest run of identical readings is past reading.stuck_limit.
+    pub fn is_stuck(&self, swarm_id: &str) -> bool {
+        let limit = self.cfg.reading.stuck_limit;
+        limit > 0
+            && self
+                .repeated_readings
+                .get(swarm_id)
+                .is_some_and(|&(_, repeats)| repeats > limit)
+    }
+
+    // Record a packet's seq; returns (missing, lost so far) when it skips ahead by more than 1.
+    // A seq at or below the last one (board reboot, reordering) restarts tracking from it.
+    pub fn note_seq(&mut self, swarm_id: &str, seq: u64) -> Option<(u64, u64)> {
+        let Some(track) = self.swarm_seq.get_mut(swarm_id) else {
+            self.swarm_seq
+                .insert(swarm_id.to_string(), SeqTrack { last: seq, lost: 0 });
+            return None;
+        };
+        let missing = seq.saturating_sub(track.last).saturating_sub(1);
+        track.last = seq;
+        if missing == 0 {
+            return None;
+        }
+        track.lost += missing;
+        Some((missing, track.lost))
+    }
+
+    // Snapshot of a swarm's recent readings, oldest first (empty for unknown ids).
+    pub fn recent_readings(&self, swarm_id: &str) -> Vec<(u128, f64)> {
+        self.recent_readings
+            .get(swarm_id)
+            .map_or_else(Vec::new, |recent| recent.iter().copied().collect())
+    }
+
+    // log.record_seq numbers the records of one log: they restart only when it is cleared.
+    pub fn log_cleared(&mut self) {
+        self.log_record_seq = 0;
+    }
+
+    pub fn next_log_record_seq(&mut self) -> u64 {
+        let seq = self.log_record_seq;
+        self.log_record_seq += 1;
+        seq
+    }
+
+    pub fn prune_master_changes(&mut self, now: Instant) {
+        let window = Duration::from_millis(self.cfg.flap.window_ms);
+        while let Some(&t) = self.master_change_times.front() {
+            if now.duration_since(t) <= window {
+                break;
+            }
+            self.master_change_times.pop_front();
+        }
+    }
+
+    // Record a master switch; returns the change count when it starts stabilization.
+    pub fn flap_check(&mut self, now: Instant) -> Option<usize> {
+        self.master_change_times.push_back(now);
+        self.prune_master_changes(now);
+        if self.stabilize_until.is_some() {
+            return None;
+        }
+        let changes = self.master_change_times.len();
+        if changes < self.cfg.flap.threshold {
+            return None;
+        }
+        self.stabilize_until = Some(now + Duration::from_millis(self.cfg.flap.hold_ms));
+        Some(changes)
+    }
+
+    // Once the hold expires, leave stabilization if flapping has subsided (else extend).
+    pub fn flap_subsided(&mut self, now: Instant) -> bool {
+        match self.stabilize_until {
+            Some(until) if now >= until => {
+                self.prune_master_changes(now);
+                if self.master_change_times.len() >= self.cfg.flap.threshold {
+                    self.stabilize_until = Some(now + Duration::from_millis(self.cfg.flap.hold_ms));
+                    false
+                } else {
+                    self.stabilize_until = None;
+                    true
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BlinkConfig;
+
+    fn state_with_leds(pins: Vec<u32>) -> SharedState {
+        let mut cfg = Config::default();
+        cfg.gpio.rgb_led_pins = pins;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        SharedState::new(Arc::new(cfg), blink_map)
+    }
+
+    #[test]
+    fn swarm_colors_are_pinned_or_distinct_and_stable() {
+        let mut cfg = Config::default();
+        cfg.gpio
+            .swarm_colors
+            .insert("a".to_string(), RgbColor([0, 0, 255]));
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+
+        assert_eq!(st.swarm_color("a"), RgbColor([0, 0, 255]));
+        // blue is pinned to a: the others start at green
+        assert_eq!(st.swarm_color("b"), RgbColor([0, 255, 0]));
+        assert_eq!(st.swarm_color("c"), RgbColor([255, 0, 0]));
+        st.reset();
+        assert_eq!(st.swarm_color("b"), RgbColor([0, 255, 0]));
+        // more swarms than colors: they are reused rather than running out
+        for i in 0..AUTO_COLORS.len() {
+            st.swarm_color(&format!("x{i}"));
+        }
+        assert_eq!(st.auto_colors.len(), AUTO_COLORS.len() + 2);
+    }
+
+    #[test]
+    fn every_recent_master_blinks_its_own_led() {
+        let mut st = state_with_leds(vec![1, 2, 3]);
+        let now = Instant::now();
+        st.note_blinking_master("a", 100.0, now);
+        st.note_blinking_master("b", 900.0, now);
+        st.active_led = Some(st.swarm_to_led["b"]);
+        let others = st.other_masters(now);
+        assert_eq!(others.len(), 1);
+        let (idx, reading, interval) = others[0];
+        assert_eq!((idx, reading), (st.swarm_to_led["a"], 100.0));
+        assert!(interval > st.blinking_masters[&st.swarm_to_led["b"]].interval);
+
+        // silent past master.timeout_ms: no longer blinking
+        let later = now + Duration::from_millis(st.cfg.master.timeout_ms);
+        assert!(st.other_masters(later).is_empty());
+        assert!(st.blinking_masters.is_empty());
+
+        let mut cfg = Config::default();
+        cfg.master.single = true;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        st.note_blinking_master("a", 100.0, now);
+        assert!(st.other_masters(now).is_empty());
+    }
+
+    #[test]
+    fn recent_readings_keep_the_latest_capacity() {
+        let mut cfg = Config::default();
+        cfg.history.capacity = 3;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        for reading in 1..=5 {
+            st.record_reading("a", f64::from(reading));
+        }
+        st.record_reading("b", 9.5);
+        let readings: Vec<f64> = st.recent_readings("a").iter().map(|r| r.1).collect();
+        assert_eq!(readings, [3.0, 4.0, 5.0]);
+        assert_eq!(st.recent_readings("b").len(), 1);
+        assert!(st.recent_readings("c").is_empty());
+    }
+
+    #[test]
+    fn alarm_has_hysteresis() {
+        let mut cfg = Config::default();
+        cfg.alarm.low = Some(100.0);
+        cfg.alarm.high = Some(900.0);
+        cfg.alarm.hysteresis = 20;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        assert_eq!(st.check_alarm(500.0), None);
+        assert_eq!(st.check_alarm(905.0), Some((AlarmBound::High, 900.0)));
+        assert!(std::mem::take(&mut st.alarm_flash));
+        // chatter just below the bound stays the same alarm
+        assert_eq!(st.check_alarm(895.0), None);
+        assert_eq!(st.check_alarm(901.0), None);
+        assert!(!st.alarm_flash);
+        assert_eq!(st.check_alarm(880.0), None);
+        assert_eq!(st.alarm, None);
+        assert_eq!(st.check_alarm(950.0), Some((AlarmBound::High, 900.0)));
+        // straight to the other bound
+        assert_eq!(st.check_alarm(50.0), Some((AlarmBound::Low, 100.0)));
+        assert_eq!(st.check_alarm(110.0), None);
+        assert_eq!(st.alarm, Some(AlarmBound::Low));
+    }
+
+    #[test]
+    fn identical_readings_flag_a_stuck_sensor() {
+        let mut cfg = Config::default();
+        cfg.reading.stuck_limit = 3;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        for _ in 0..3 {
+            assert_eq!(st.note_repeat("a", 512.0), None);
+        }
+        assert!(!st.is_stuck("a"));
+        assert_eq!(st.note_repeat("a", 512.0), Some(4));
+        assert!(st.is_stuck("a"));
+        // reported once per run; other swarms are counted separately
+        assert_eq!(st.note_repeat("a", 512.0), None);
+        assert_eq!(st.note_repeat("b", 512.0), None);
+        assert!(!st.is_stuck("b"));
+        // a changed reading starts a new run
+        assert_eq!(st.note_repeat("a", 513.0), None);
+        assert!(!st.is_stuck("a"));
+    }
+
+    #[test]
+    fn reset_cooldown_ends_at_its_deadline() {
+        let mut st = state_with_leds(vec![17]);
+        let now = Instant::now();
+        assert!(!st.in_reset_cooldown(now));
+        st.reset_cooldown_until = Some(now + Duration::from_millis(500));
+        assert!(st.in_reset_cooldown(now));
+        assert!(!st.in_reset_cooldown(now + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn alarm_is_off_by_default() {
+        let mut st = state_with_leds(vec![17]);
+        assert_eq!(st.check_alarm(f64::MIN), None);
+        assert_eq!(st.check_alarm(f64::MAX), None);
+    }
+
+    #[test]
+    fn master_dwell_adds_up_per_swarm() {
+        let mut st = state_with_leds(vec![17]);
+        let start = Instant::now();
+        st.record_reading("a", 1.0);
+        st.last_master_id = Some("a".to_string());
+        st.master_since = Some(start);
+        let stats = st.swarm_stats_at(start + Duration::from_secs(3));
+        assert_eq!(stats["a"].master_time, Duration::from_secs(3));
+        // the running dwell is only counted in once it ends
+        assert_eq!(st.swarm_stats["a"].master_time, Duration::ZERO);
+        let dwell = st.end_master_dwell(start + Duration::from_secs(5));
+        assert_eq!(dwell, Some(Duration::from_secs(5)));
+        assert_eq!(st.swarm_stats["a"].master_time, Duration::from_secs(5));
+        assert_eq!(st.end_master_dwell(start + Duration::from_secs(9)), None);
+    }
+
+    #[test]
+    fn log_record_seq_restarts_only_with_the_log() {
+        let mut st = state_with_leds(vec![17]);
+        assert_eq!(st.next_log_record_seq(), 0);
+        assert_eq!(st.next_log_record_seq(), 1);
+        st.reset();
+        assert_eq!(st.next_log_record_seq(), 2);
+        st.log_cleared();
+        assert_eq!(st.next_log_record_seq(), 0);
+    }
+
+    #[test]
+    fn sequence_gaps_count_missing_packets() {
+        let mut st = state_with_leds(vec![17]);
+        assert_eq!(st.note_seq("a", 1), None);
+        assert_eq!(st.note_seq("a", 2), None);
+        assert_eq!(st.note_seq("a", 5), Some((2, 2)));
+        assert_eq!(st.note_seq("b", 9), None);
+        // restart after a reboot, then another gap
+        assert_eq!(st.note_seq("a", 0), None);
+        assert_eq!(st.note_seq("a", 2), Some((1, 3)));
+    }
+
+    #[test]
+    fn single_led_maps_every_swarm_to_index_0() {
+        let mut st = state_with_leds(vec![17]);
+        for id in ["a", "b", "c", "d"] {
+            assert_eq!(st.assign_led_index(id), 0);
+        }
+    }
+
+    #[test]
+    fn pinned_and_restored_leds_are_kept() {
+        let mut cfg = Config::default();
+        cfg.gpio.led_assignments = HashMap::from([("kitchen".to_string(), 2)]);
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        st.restore_led_map(HashMap::from([
+            ("a".to_string(), 1),
+            ("kitchen".to_string(), 0),
+            ("gone".to_string(), 7),
+        ]));
+        assert!(!st.swarm_to_led.contains_key("gone"));
+        assert_eq!(st.assign_led_index("a"), 1);
+        assert!(!st.led_map_changed);
+        // the pinned index wins over the restored one
+        assert_eq!(st.assign_led_index("kitchen"), 2);
+        assert!(std::mem::take(&mut st.led_map_changed));
+        // LED2 is pinned, so a new swarm gets the next free one
+        assert_eq!(st.assign_led_index("new"), 0);
+        assert!(st.led_map_changed);
+        // without a led_map_file, a reset forgets the assignments
+        st.reset();
+        assert_eq!(st.assign_led_index("a"), 0);
+    }
+
+    #[test]
+    fn dynamic_assignment_skips_pinned_leds() {
+        let mut cfg = Config::default();
+        cfg.gpio.rgb_led_pins = vec![5, 6, 13, 19];
+        cfg.gpio.led_assignments =
+            HashMap::from([("kitchen".to_string(), 1), ("hall".to_string(), 2)]);
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        st.restore_led_map(HashMap::from([("old".to_string(), 2)]));
+        assert!(st.swarm_to_led.is_empty());
+        let idx: Vec<usize> = ["a", "kitchen", "b", "c", "hall"]
+            .iter()
+            .map(|id| st.assign_led_index(id))
+            .collect();
+        assert_eq!(idx, [0, 1, 3, 0, 2]);
+
+        // every LED pinned: the others share them round-robin
+        let mut cfg = Config::default();
+        cfg.gpio.rgb_led_pins = vec![5];
+        cfg.gpio.led_assignments = HashMap::from([("kitchen".to_string(), 0)]);
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        assert_eq!(st.assign_led_index("a"), 0);
+    }
+
+    #[test]
+    fn led_assignment_wraps_at_configured_pin_count() {
+        let mut st = state_with_leds(vec![5, 6, 13, 19, 26]);
+        let idx: Vec<usize> = ["a", "b", "c", "d", "e", "f", "a"]
+            .iter()
+            .map(|id| st.assign_led_index(id))
+            .collect();
+        assert_eq!(idx, [0, 1, 2, 3, 4, 0, 0]);
+        assert_eq!(SharedState::led_label(4), "LED4");
+    }
+
+    #[test]
+    fn new_master_needs_consecutive_packets() {
+        let mut cfg = Config::default();
+        cfg.master.confirm_packets = 3;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        let now = Instant::now();
+
+        // the first master is taken immediately
+        assert!(st.confirm_master("a", now));
+        st.last_master_id = Some("a".to_string());
+
+        assert!(!st.confirm_master("b", now));
+        assert!(!st.confirm_master("b", now));
+        // a packet from the current master breaks the streak
+        assert!(st.confirm_master("a", now));
+        assert!(!st.confirm_master("b", now));
+        assert!(!st.confirm_master("b", now));
+        assert!(st.confirm_master("b", now));
+    }
+
+    #[test]
+    fn packets_per_second_covers_the_last_second() {
+        let mut st = state_with_leds(vec![17]);
+        let t0 = Instant::now();
+        for ms in [0, 200, 400, 900] {
+            st.note_received(t0 + Duration::from_millis(ms));
+        }
+        assert_eq!(st.packets_per_second(t0 + Duration::from_millis(950)), 4);
+        assert_eq!(st.packets_per_second(t0 + Duration::from_millis(1300)), 2);
+        st.note_received(t0 + Duration::from_millis(2500));
+        assert_eq!(st.recent_packets.len(), 1);
+        assert_eq!(st.packets_received, 5);
+    }
+
+    #[test]
+    fn roster_lists_every_swarm_heard_from() {
+        let mut st = state_with_leds(vec![17, 22]);
+        let later = Instant::now() + Duration::from_millis(st.cfg.status.roster_ms * 2);
+        // nothing heard yet
+        assert_eq!(st.take_roster(later), None);
+
+        st.record_reading("b", 10.0);
+        st.record_reading("a", 20.0);
+        st.assign_led_index("a");
+        let roster = st.take_roster(later).unwrap();
+        let ids: Vec<&str> = roster.iter().map(|e| e.swarm_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(roster[0].led_index, Some(0));
+        assert_eq!(roster[1].led_index, None);
+        assert!(roster[1].last_seen_secs >= st.cfg.status.roster_ms / 1000);
+        // the next one is a full period away
+        assert_eq!(st.take_roster(Instant::now()), None);
+    }
+
+    #[test]
+    fn diagnostics_only_when_drops_changed() {
+        let mut st = state_with_leds(vec![17, 22]);
+        let later = Instant::now() + Duration::from_millis(st.cfg.status.diagnostics_ms * 2);
+        assert_eq!(st.take_diagnostics(later), None);
+
+        st.decode_drops.bad_format += 2;
+        st.decode_drops.rejected_role += 1;
+        // not due yet
+        assert_eq!(st.take_diagnostics(Instant::now()), None);
+        let drops = st.take_diagnostics(later).unwrap();
+        assert_eq!(
+            (drops.bad_utf8, drops.bad_format, drops.rejected_role),
+            (0, 2, 1)
+        );
+        // unchanged: nothing to report
+        assert_eq!(st.take_diagnostics(later + Duration::from_secs(120)), None);
+    }
+
+    #[test]
+    fn heartbeat_is_due_without_traffic_and_survives_resets() {
+        let mut st = state_with_leds(vec![17, 22]);
+        let later = Instant::now() + Duration::from_millis(st.cfg.status.heartbeat_ms * 2);
+        assert!(!st.heartbeat_due(Instant::now()));
+        st.reset();
+        assert!(st.heartbeat_due(later));
+        // the next one is a full period away
+        assert!(!st.heartbeat_due(Instant::now()));
+    }
+
+    #[test]
+    fn status_prints_follow_the_configured_rate() {
+        let mut cfg = Config::default();
+        cfg.status.jitter_ms = 0;
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        let mut st = SharedState::new(Arc::new(cfg.clone()), blink_map);
+        let period = Duration::from_millis(cfg.status.print_ms);
+        assert!(!st.status_due(Instant::now()));
+        assert!(st.status_due(Instant::now() + period));
+        assert!(!st.status_due(Instant::now()));
+
+        cfg.status.print_ms = 0;
+        let mut st = SharedState::new(Arc::new(cfg.clone()), blink_map);
+        assert!(!st.status_due(Instant::now() + Duration::from_secs(3600)));
+
+        // long intervals neither overflow nor come due
+        cfg.status.print_ms = u64::MAX;
+        let mut st = SharedState::new(Arc::new(cfg), blink_map);
+        assert!(!st.status_due(Instant::now() + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn silent_master_times_out_once() {
+        let mut st = state_with_leds(vec![17, 22, 27]);
+        let seen = Instant::now();
+        st.last_master_id = Some("a".to_string());
+        st.last_master_seen = Some(seen);
+        st.active_led = Some(0);
+
+        let timeout = Duration::from_millis(st.cfg.master.timeout_ms);
+        assert_eq!(st.take_stale_master(seen + timeout / 2), None);
+        assert_eq!(st.take_stale_master(seen + timeout), Some("a".to_string()));
+        assert_eq!(st.last_master_id, None);
+        assert_eq!(st.active_led, None);
+        assert_eq!(st.take_stale_master(seen + timeout * 2), None);
+    }
+}
@@ -0,0 +1,143 @@
+// Per-swarm reading statistics, summarized on shutdown and on SIGUSR1.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwarmStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    // running mean, so a long session never has to keep every reading
+    pub mean: f64,
+    // total time the swarm has been Master (finished dwells only, see SharedState)
+    pub master_time: Duration,
+}
+
+impl SwarmStats {
+    pub fn new(reading: f64) -> Self {
+        Self {
+            count: 1,
+            min: reading,
+            max: reading,
+            mean: reading,
+            master_time: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, reading: f64) {
+        self.count += 1;
+        self.min = self.min.min(reading);
+        self.max = self.max.max(reading);
+        self.mean += (reading - self.mean) / self.count as f64;
+    }
+}
+
+// Mean of the latest `window` readings: a ring buffer plus a running sum, so O(1) per reading.
+#[derive(Debug, Clone)]
+pub struct MovingAverage {
+    window: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+}
+
+impl MovingAverage {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            values: VecDeque::with_capacity(window),
+            sum: 0.0,
+        }
+    }
+
+    // Adds a reading and returns the window's mean.
+    pub fn push(&mut self, reading: f64) -> f64 {
+        if self.values.len() == self.window {
+            if let Some(oldest) = self.values.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+        self.values.push_back(reading);
+        self.sum += reading;
+        self.sum / self.values.len() as f64
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.sum = 0.0;
+    }
+}
+
+// Summary table, one row per swarm, highest max reading first.
+pub fn summary_lines(stats: &HashMap<String, SwarmStats>) -> Vec<String> {
+    let mut rows: Vec<_> = stats.iter().collect();
+    rows.sort_by(|a, b| b.1.max.total_cmp(&a.1.max).then_with(|| a.0.cmp(b.0)));
+    let width = rows
+        .iter()
+        .map(|(id, _)| id.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    let mut lines = vec![format!(
+        "{:<width$}  {:>8}  {:>6}  {:>6}  {:>8}  {:>9}",
+        "swarm", "count", "min", "max", "avg", "master"
+    )];
+    for (id, s) in rows {
+        let master = format!("{:.1}s", s.master_time.as_secs_f64());
+        lines.push(format!(
+            "{id:<width$}  {:>8}  {:>6}  {:>6}  {:>8.1}  {master:>9}",
+            s.count, s.min, s.max, s.mean
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_mean_min_max() {
+        let mut s = SwarmStats::new(100.0);
+        for r in [300.0, 200.0, 400.5] {
+            s.record(r);
+        }
+        assert_eq!((s.count, s.min, s.max), (4, 100.0, 400.5));
+        assert!((s.mean - 250.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moving_average_covers_the_window() {
+        let mut avg = MovingAverage::new(3);
+        assert_eq!(avg.push(100.0), 100.0);
+        assert_eq!(avg.push(200.0), 150.0);
+        assert_eq!(avg.push(600.0), 300.0);
+        // 100 drops out
+        assert_eq!(avg.push(700.0), 500.0);
+        avg.clear();
+        assert_eq!(avg.push(10.0), 10.0);
+        assert_eq!(avg.push(10.5), 10.25);
+
+        // a window of 1 passes readings through
+        let mut avg = MovingAverage::new(1);
+        assert_eq!(avg.push(5.0), 5.0);
+        assert_eq!(avg.push(900.25), 900.25);
+    }
+
+    #[test]
+    fn summary_lists_highest_max_first() {
+        let mut high = SwarmStats::new(900.0);
+        high.master_time = Duration::from_millis(12_340);
+        let stats = HashMap::from([
+            ("low".to_string(), SwarmStats::new(10.0)),
+            ("high".to_string(), high),
+        ]);
+        let lines = summary_lines(&stats);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("swarm"));
+        assert!(lines[1].starts_with("high ") && lines[1].ends_with(" 12.3s"));
+        assert!(lines[2].starts_with("low ") && lines[2].ends_with(" 0.0s"));
+    }
+}
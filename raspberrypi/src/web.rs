@@ -0,0 +1,248 @@
+use log::{debug, info, warn};
+use raspberrypi::event::Event;
+use raspberrypi::fanout::{Fanout, Slots};
+use raspberrypi::state::SharedState;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Events queued per client before further ones are dropped for it (a slow browser).
+const CLIENT_QUEUE: usize = 64;
+// A comment line after this long without events, so closed connections are noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+// /events streams at once, each a thread; more are refused with 503.
+const MAX_CLIENTS: usize = 8;
+
+// One queue per connected /events client. Events are emitted from every thread (receive loop,
+// blink timer, GPIO thread), so publishing goes through this instead of a handle passed around.
+static CLIENTS: Fanout<SyncSender<String>> = Fanout::new();
+static SLOTS: Slots = Slots::new(MAX_CLIENTS);
+
+// The page served on /: the current Master and blink interval, and the latest events.
+const PAGE: &str = r#"<!doctype html>
+<meta charset="utf-8">
+<title>LightSwarm</title>
+<h1>Master: <span id="master">none</span></h1>
+<p>Blink interval: <span id="blink">-</span></p>
+<pre id="events"></pre>
+<script>
+const master = document.getElementById("master");
+const blink = document.getElementById("blink");
+const events = document.getElementById("events");
+new EventSource("/events").onmessage = (message) => {
+  const event = JSON.parse(message.data);
+  if ("blink_ms" in event) {
+    master.textContent = event.swarm_id;
+    blink.textContent = event.blink_ms + " ms";
+  } else if (event.event === "master_timeout" || event.event === "reset") {
+    master.textContent = "none";
+    blink.textContent = "-";
+  }
+  events.textContent = (message.data + "\n" + events.textContent).slice(0, 20000);
+};
+</script>
+"#;
+
+// Hands an event to every /events client without waiting: a full queue drops it for that
+// client, a closed one is forgotten. Nothing to do while no one is connected.
+pub fn publish(event: &Event) {
+    CLIENTS.publish(|| event.to_json());
+}
+
+// HTTP on network.web_port: / (the page) and /events (Server-Sent Events, one JSON event per
+// message, starting with the current Master's STATUS when there is one).
+pub fn serve(server: &tiny_http::Server, state: &Mutex<SharedState>, shutdown: &AtomicBool) {
+    while !shutdown.load(Ordering::SeqCst) {
+        let request = match server.recv_timeout(Duration::from_millis(100)) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Web server error: {e}");
+                return;
+            }
+        };
+        let response = match request.url() {
+            "/events" => {
+                let Some(slot) = SLOTS.take() else {
+                    debug!("Web: event stream refused, {MAX_CLIENTS} already open");
+                    let busy = tiny_http::Response::from_string("too many event streams")
+                        .with_status_code(503);
+                    if let Err(e) = request.respond(busy) {
+                        debug!("Web response failed: {e}");
+                    }
+                    continue;
+                };
+                let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+                let snapshot = current_status(&state.lock().unwrap());
+                CLIENTS.subscribe(tx);
+                let peer = request
+                    .remote_addr()
+                    .map_or_else(|| "?".to_string(), ToString::to_string);
+                info!("Web: event stream to {peer}");
+                // each stream blocks on its queue in its own thread, never in the event's path; the
+                // slot is given back when the client goes away and the thread ends
+                thread::spawn(move || {
+                    let _slot = slot;
+                    if let Err(e) = stream_events(request, snapshot, &rx) {
+                        debug!("Web: event stream to {peer} closed: {e}");
+                    }
+                });
+                continue;
+            }
+            "/" => {
+                let content_type =
+                    tiny_http::Header::from_bytes("Content-Type", "text/html; charset=utf-8")
+                        .expect("static header");
+                tiny_http::Response::from_string(PAGE).with_header(content_type)
+            }
+            _ => tiny_http::Response::from_string("not found").with_status_code(404),
+        };
+        if let Err(e) = request.respond(response) {
+            debug!("Web response failed: {e}");
+        }
+    }
+}
+
+// The response is written by hand: tiny_http's chunked bodies are buffered, which would hold
+// events back.
+fn stream_events(
+    request: tiny_http::Request,
+    snapshot: Option<Event>,
+    rx: &mpsc::Receiver<String>,
+) -> io::Result<()> {
+    let mut out = request.into_writer();
+    out.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    )?;
+    if let Some(event) = snapshot {
+        write!(out, "data: {}\n\n", event.to_json())?;
+    }
+    out.flush()?;
+    loop {
+        match rx.recv_timeout(KEEPALIVE) {
+            Ok(json) => write!(out, "data: {json}\n\n")?,
+            Err(RecvTimeoutError::Timeout) => out.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        out.flush()?;
+    }
+}
+
+// The current Master's view as a STATUS event, for a client that just connected.
+pub fn current_status(st: &SharedState) -> Option<Event> {
+    let now = Instant::now();
+    Some(Event::Status {
+        ts_ms: st.ts_ms(),
+        swarm_id: st.last_master_id.clone()?,
+        sender: st.last_master_sender?,
+        reading: st.last_reading?,
+        led_index: st.active_led?,
+        blink_ms: st.blink_interval?.as_millis() as u64,
+        pps: st.packets_per_second(now),
+        uptime_secs: st.start.elapsed().as_secs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raspberrypi::blink::BlinkMap;
+    use raspberrypi::config::{BlinkConfig, Config};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn state() -> SharedState {
+        let blink_map = BlinkMap::from_config(&BlinkConfig::default()).unwrap();
+        SharedState::new(Arc::new(Config::default()), blink_map)
+    }
+
+    #[test]
+    fn status_needs_a_master() {
+        let mut st = state();
+        assert_eq!(current_status(&st), None);
+        st.last_master_id = Some("abc".to_string());
+        st.last_master_sender = Some("10.0.0.5".parse().unwrap());
+        st.last_reading = Some(512.0);
+        st.active_led = Some(1);
+        // no blink interval yet
+        assert_eq!(current_status(&st), None);
+        st.blink_interval = Some(Duration::from_millis(250));
+        let Some(Event::Status {
+            swarm_id,
+            reading,
+            led_index,
+            blink_ms,
+            ..
+        }) = current_status(&st)
+        else {
+            panic!("no status");
+        };
+        assert_eq!(swarm_id, "abc");
+        assert_eq!(reading, 512.0);
+        assert_eq!(led_index, 1);
+        assert_eq!(blink_ms, 250);
+    }
+
+    #[test]
+    fn event_streams_past_the_limit_are_refused() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let state = Mutex::new(state());
+        let shutdown = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| serve(&server, &state, &shutdown));
+            // the connection and its status line
+            let open = || {
+                let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+                conn.write_all(b"GET /events HTTP/1.1\r\nHost: pi\r\n\r\n")
+                    .unwrap();
+                let mut conn = BufReader::new(conn);
+                let mut status = String::new();
+                conn.read_line(&mut status).unwrap();
+                (conn, status)
+            };
+            let streams: Vec<_> = (0..MAX_CLIENTS).map(|_| open()).collect();
+            assert!(streams
+                .iter()
+                .all(|(_, status)| status.starts_with("HTTP/1.1 200")));
+            assert!(open().1.starts_with("HTTP/1.1 503"));
+
+            // a closed stream's slot is free again once writing to it fails
+            let close_all = || {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while SLOTS.in_use() > 0 && Instant::now() < deadline {
+                    publish(&Event::GpioChannelClosed { ts_ms: 0 });
+                    thread::sleep(Duration::from_millis(10));
+                }
+                // forgets the last of them
+                publish(&Event::GpioChannelClosed { ts_ms: 0 });
+            };
+            drop(streams);
+            close_all();
+            let (mut conn, status) = open();
+            assert!(status.starts_with("HTTP/1.1 200"));
+
+            // after the headers, one "data:" message per event
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                conn.read_line(&mut line).unwrap();
+            }
+            let event = Event::GpioChannelClosed { ts_ms: 7 };
+            publish(&event);
+            let mut message = String::new();
+            for _ in 0..2 {
+                conn.read_line(&mut message).unwrap();
+            }
+            assert_eq!(message, format!("data: {}\n\n", event.to_json()));
+            drop(conn);
+            close_all();
+            assert!(CLIENTS.is_empty());
+            shutdown.store(true, Ordering::SeqCst);
+        });
+    }
+}
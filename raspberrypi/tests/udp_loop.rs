@@ -0,0 +1,198 @@
+// End-to-end: the binary in simulation mode on an ephemeral port, fed real UDP datagrams.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// How long to wait for the instance to start or answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Instance {
+    child: Child,
+    // console lines (LED writes show up as set_led lines)
+    lines: mpsc::Receiver<String>,
+    addr: SocketAddr,
+    // the TCP ingest port, when network.tcp_ingest_port is set
+    tcp_port: Option<u16>,
+    dir: PathBuf,
+}
+
+impl Instance {
+    fn start(name: &str, args: &[&str]) -> Self {
+        Self::start_with_config(name, "", args)
+    }
+
+    // `config` is written to the instance's config file (empty: all defaults).
+    fn start_with_config(name: &str, config: &str, args: &[&str]) -> Self {
+        let dir = std::env::temp_dir().join(format!("lightswarm-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lightswarm.toml"), config).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_raspberrypi"))
+            .args(["--simulate", "--no-selftest", "--port", "0"])
+            .args(["--bind", "127.0.0.1", "--config", "lightswarm.toml"])
+            .args(["--log-file", "readings.txt"])
+            .args(args)
+            .current_dir(&dir)
+            .env("RUST_LOG", "info")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut tcp_port = None;
+        let addr = loop {
+            let line = lines.recv_timeout(TIMEOUT).expect("no listener line");
+            if let Some((_, port)) = line.split_once("RPI TCP ingest on port ") {
+                tcp_port = Some(port.trim().parse().unwrap());
+            }
+            if let Some((_, addr)) = line.split_once("RPI UDP listener on ") {
+                break addr.trim().parse().unwrap();
+            }
+        };
+        Self {
+            child,
+            lines,
+            addr,
+            tcp_port,
+            dir,
+        }
+    }
+
+    fn tcp_client(&self) -> TcpStream {
+        let port = self.tcp_port.expect("no TCP ingest listener");
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream
+    }
+
+    fn client(&self) -> UdpSocket {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sock.set_read_timeout(Some(TIMEOUT)).unwrap();
+        sock.connect(self.addr).unwrap();
+        sock
+    }
+
+    // SIGTERM, so the log is flushed, then everything the instance printed.
+    fn stop(mut self) -> (Vec<String>, String) {
+        // SAFETY: plain kill(2) on our own child process
+        unsafe { libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM) };
+        assert!(self.child.wait().unwrap().success());
+        let lines = self.lines.iter().collect();
+        let log = fs::read_to_string(self.dir.join("readings.txt")).unwrap_or_default();
+        let _ = fs::remove_dir_all(&self.dir);
+        (lines, log)
+    }
+}
+
+fn recv(sock: &UdpSocket) -> String {
+    let mut buf = [0u8; 512];
+    let n = sock.recv(&mut buf).expect("no reply");
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+#[test]
+fn logs_and_acks_accepted_readings() {
+    let instance = Instance::start("ack", &["--ack"]);
+    let sock = instance.client();
+    sock.send(b"+++Master,abc,512***").unwrap();
+    assert_eq!(recv(&sock), "+++ACK,abc,512***");
+    // garbage is dropped without an ACK
+    sock.send(b"hello").unwrap();
+    sock.send(b"+++Slave,def,300***").unwrap();
+    assert_eq!(recv(&sock), "+++ACK,def,300***");
+    sock.send(b"+++STATUS?***").unwrap();
+    assert!(recv(&sock).starts_with("+++STATUS,abc,512,0,"));
+
+    let (lines, log) = instance.stop();
+    let records: Vec<&str> = log
+        .lines()
+        .map(|line| line.split_once(' ').unwrap().1)
+        .collect();
+    assert_eq!(
+        records,
+        [
+            "Swarm ID abc: 512 sender=127.0.0.1",
+            "Swarm ID def: 300 sender=127.0.0.1 role=Slave",
+        ]
+    );
+    assert!(lines.iter().any(|l| l.contains("EVENT master_set  to=abc")));
+    assert!(lines.iter().any(|l| l.contains("bad_format=1")));
+}
+
+#[test]
+fn tcp_acks_only_frames_that_were_handled() {
+    let config = "[network]\ntcp_ingest_port = 0\ntcp_ack = true\n";
+    let instance = Instance::start_with_config("tcp-ack", config, &[]);
+    let mut stream = instance.tcp_client();
+    stream.write_all(b"+++Master,abc,512***").unwrap();
+    let mut ack = [0u8; 17];
+    stream.read_exact(&mut ack).unwrap();
+    assert_eq!(&ack, b"+++ACK,abc,512***");
+    // out of range (skipped) and unparsable frames are not confirmed
+    stream
+        .write_all(b"+++Master,abc,5000***+++Master,abc***")
+        .unwrap();
+    stream.write_all(b"+++Slave,def,300***").unwrap();
+    stream.read_exact(&mut ack).unwrap();
+    assert_eq!(&ack, b"+++ACK,def,300***");
+
+    let (_, log) = instance.stop();
+    assert_eq!(log.lines().count(), 2);
+}
+
+#[test]
+fn tcp_connections_past_the_limit_are_closed() {
+    let config = "[network]\ntcp_ingest_port = 0\ntcp_ack = true\n";
+    let instance = Instance::start_with_config("tcp-limit", config, &[]);
+    let mut served: Vec<_> = (0..16).map(|_| instance.tcp_client()).collect();
+    // the 17th is closed without being read
+    let mut refused = instance.tcp_client();
+    assert_eq!(refused.read(&mut [0u8; 1]).unwrap(), 0);
+    let last = served.last_mut().unwrap();
+    last.write_all(b"+++Master,abc,512***").unwrap();
+    let mut ack = [0u8; 17];
+    last.read_exact(&mut ack).unwrap();
+    assert_eq!(&ack, b"+++ACK,abc,512***");
+
+    let (_, log) = instance.stop();
+    assert_eq!(log.lines().count(), 1);
+}
+
+#[test]
+fn blinks_at_the_reading_rate_not_the_packet_rate() {
+    let instance = Instance::start("blink", &[]);
+    let sock = instance.client();
+    // the lowest reading blinks every 2 s, however fast it is sent
+    for _ in 0..20 {
+        sock.send(b"+++Master,abc,24***").unwrap();
+        thread::sleep(Duration::from_millis(25));
+    }
+
+    let (lines, log) = instance.stop();
+    assert_eq!(log.lines().count(), 20);
+    let led_on = lines
+        .iter()
+        .filter(|l| l.ends_with("set_led idx=0 on=true"))
+        .count();
+    assert!(led_on <= 1, "LED0 switched on {led_on} times in 0.5 s");
+    // only the Master's LED is driven
+    let others_on = lines
+        .iter()
+        .filter(|l| l.ends_with("on=true") && !l.contains("idx=0"));
+    assert_eq!(others_on.count(), 0);
+}